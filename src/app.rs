@@ -1,94 +1,162 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
 
-use crate::renderer::Renderer;
+use crate::{
+    clock::FrameClock,
+    renderer::prelude::{Renderer, RendererError},
+};
 
 pub trait AppState {
     fn init() -> Self;
     fn input(&mut self, event: &WindowEvent, window: &Window) -> bool;
-    fn update(&mut self, window: &Window);
+    // Exports substitute a deterministic clock, so animations should derive
+    // from the clock instead of counting frames
+    fn update(&mut self, clock: &FrameClock);
 }
 
 pub trait AppView<State: AppState> {
     fn init(renderer: &mut Renderer, state: &State) -> Self;
     fn resize(&mut self, window: &Window);
-    fn render(&mut self, renderer: &mut Renderer, state: &State) -> Result<(), wgpu::SurfaceError>;
+    // Views meant for `TiledExport` must multiply their projections by
+    // `renderer.tile().clip_transform()`, otherwise every tile shows the
+    // whole canvas
+    fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        state: &State,
+        clock: &FrameClock,
+    ) -> Result<(), RendererError>;
 }
 
 pub struct App {
     pub window: Window,
     pub renderer: Renderer,
+    // Realtime by default, replace it before `run` for a fixed step or
+    // scaled time
+    pub clock: FrameClock,
     event_loop: EventLoop<()>,
 }
 
 impl App {
-    pub async fn new(window_builder: WindowBuilder) -> App {
+    pub async fn new(window_builder: WindowBuilder) -> Result<App, RendererError> {
         env_logger::init();
         let event_loop = EventLoop::new();
-        let window = window_builder.build(&event_loop).unwrap();
+        let window = window_builder.build(&event_loop)?;
 
-        let renderer = Renderer::new(&window).await;
+        let renderer = Renderer::new(&window).await?;
 
-        Self {
+        Ok(Self {
             window,
             renderer,
+            clock: FrameClock::new(),
             event_loop,
-        }
+        })
     }
 }
 
+// Keys not handled by `AppState::input`: Escape quits, F12 saves a
+// screenshot and Pause pauses the clock
 pub fn run<S: AppState + 'static, V: AppView<S> + 'static>(mut app: App) {
     let mut state = S::init();
     let mut view = V::init(&mut app.renderer, &state);
-    app.event_loop
-        .run(move |event, _, control_flow| match event {
+    app.event_loop.run(move |event, _, control_flow| {
+        if let Event::WindowEvent { event, window_id } = &event {
+            if *window_id == app.window.id() {
+                app.renderer.track_input(event);
+            }
+        }
+        match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == app.window.id() => {
-                if !state.input(event, &app.window) {
-                    match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
+            } if window_id == app.window.id() && !state.input(event, &app.window) => {
+                match event {
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    } => app.renderer.request_screenshot(screenshot_path()),
 
-                        WindowEvent::Resized(_physical_size) => app.renderer.resize(&app.window),
-                        WindowEvent::ScaleFactorChanged { .. } => {
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            app.renderer.resize(&app.window);
-                        }
-                        _ => {}
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Pause),
+                                ..
+                            },
+                        ..
+                    } => {
+                        app.clock.toggle_paused();
                     }
+
+                    WindowEvent::Resized(_physical_size) => app.renderer.resize(&app.window),
+                    WindowEvent::ScaleFactorChanged { .. } => {
+                        // new_inner_size is &&mut so we have to dereference it twice
+                        app.renderer.resize(&app.window);
+                    }
+                    _ => {}
                 }
             }
 
             Event::RedrawRequested(_) => {
-                state.update(&app.window);
-                match view.render(&mut app.renderer, &state) {
-                    Ok(_) => {}
+                app.clock.tick();
+                let clock = &app.clock;
+                app.renderer
+                    .set_time(clock.time(), clock.delta(), clock.frame());
+                state.update(&app.clock);
+                match view.render(&mut app.renderer, &state, &app.clock) {
+                    Ok(_) => app.renderer.end_frame(),
                     // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => app.renderer.resize(&app.window),
+                    Err(RendererError::Surface(wgpu::SurfaceError::Lost)) => {
+                        app.renderer.resize(&app.window)
+                    }
                     // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
+                    Err(RendererError::Surface(wgpu::SurfaceError::OutOfMemory)) => {
+                        *control_flow = ControlFlow::Exit
+                    }
+                    // All other surface errors (Outdated, Timeout) should be resolved by the next frame
+                    Err(e) => eprintln!("{}", e),
                 }
             }
             Event::MainEventsCleared => {
+                app.renderer.reload_modified_shades();
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
                 app.window.request_redraw();
             }
             _ => {}
-        });
+        }
+    });
+}
+
+// Screenshots go into the working directory, named after the time they were
+// taken in milliseconds, so they sort in the order they were taken
+fn screenshot_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    PathBuf::from(format!("screenshot-{}.png", millis))
 }
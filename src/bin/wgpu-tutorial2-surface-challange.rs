@@ -27,31 +27,36 @@ impl AppState for State {
         false
     }
 
-    fn update(&mut self, _window: &Window) {}
+    fn update(&mut self, _clock: &FrameClock) {}
 }
 
 struct View {
-    layer_idx: usize,
+    layer: LayerHandle,
 }
 
 impl AppView<State> for View {
     fn init(renderer: &mut Renderer, _state: &State) -> Self {
         Self {
-            layer_idx: renderer.make_layer(vec![]),
+            layer: renderer.make_layer(vec![]).unwrap(),
         }
     }
 
     fn resize(&mut self, _window: &Window) {}
 
-    fn render(&mut self, renderer: &mut Renderer, state: &State) -> Result<(), wgpu::SurfaceError> {
+    fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        state: &State,
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
         renderer
-            .layer_mut(self.layer_idx)
+            .layer_mut(self.layer)?
             .set_clear_color(Some(state.color));
-        renderer.render_layer(self.layer_idx)
+        renderer.render_layer(self.layer)
     }
 }
 
 fn main() {
-    let app = pollster::block_on(App::new(WindowBuilder::new()));
+    let app = pollster::block_on(App::new(WindowBuilder::new())).unwrap();
     run::<State, View>(app);
 }
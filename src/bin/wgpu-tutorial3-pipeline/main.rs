@@ -49,44 +49,52 @@ impl AppState for State {
         false
     }
 
-    fn update(&mut self, _window: &Window) {}
+    fn update(&mut self, _clock: &FrameClock) {}
 }
 
 struct View {
-    layer_idx1: usize,
-    layer_idx2: usize,
+    layer1: LayerHandle,
+    layer2: LayerHandle,
 }
 
 impl AppView<State> for View {
     fn init(renderer: &mut Renderer, state: &State) -> Self {
-        let form = renderer.make_form_simple_range(3);
+        let form = renderer.make_form_simple_range(3).unwrap();
 
-        let shade1 = renderer.make_shade(include_str!("shader1.wgsl"));
-        let sketch1 = renderer.make_sketch(shade1, form);
+        let shade1 = renderer.make_shade(include_str!("shader1.wgsl")).unwrap();
+        let sketch1 = renderer
+            .make_sketch(shade1, form, SketchOptions::new())
+            .unwrap();
 
-        let layer_idx1 = renderer.make_layer(vec![sketch1]);
+        let layer1 = renderer.make_layer(vec![sketch1]).unwrap();
         renderer
-            .layer_mut(layer_idx1)
+            .layer_mut(layer1)
+            .unwrap()
             .set_clear_color(Some(state.color));
 
-        let shade2 = renderer.make_shade(include_str!("shader2.wgsl"));
-        let sketch2 = renderer.make_sketch(shade2, form);
+        let shade2 = renderer.make_shade(include_str!("shader2.wgsl")).unwrap();
+        let sketch2 = renderer
+            .make_sketch(shade2, form, SketchOptions::new())
+            .unwrap();
 
-        let layer_idx2 = renderer.make_layer(vec![sketch2]);
+        let layer2 = renderer.make_layer(vec![sketch2]).unwrap();
         renderer
-            .layer_mut(layer_idx2)
+            .layer_mut(layer2)
+            .unwrap()
             .set_clear_color(Some(state.color));
 
-        Self {
-            layer_idx1,
-            layer_idx2,
-        }
+        Self { layer1, layer2 }
     }
 
-    fn render(&mut self, renderer: &mut Renderer, state: &State) -> Result<(), wgpu::SurfaceError> {
+    fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        state: &State,
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
         match state.selected {
-            SelectedSketch::One => renderer.render_layer(self.layer_idx1),
-            SelectedSketch::Two => renderer.render_layer(self.layer_idx2),
+            SelectedSketch::One => renderer.render_layer(self.layer1),
+            SelectedSketch::Two => renderer.render_layer(self.layer2),
         }
     }
 
@@ -94,6 +102,6 @@ impl AppView<State> for View {
 }
 
 fn main() {
-    let app = pollster::block_on(App::new(WindowBuilder::new()));
+    let app = pollster::block_on(App::new(WindowBuilder::new())).unwrap();
     run::<State, View>(app);
 }
@@ -12,7 +12,7 @@ struct Vertex {
 struct State {
     bg_color: wgpu::Color,
     vertices: Vec<Vertex>,
-    indices: Vec<i32>,
+    indices: Vec<u16>,
 }
 
 const COLOR: (f32, f32, f32) = (0.5, 0.0, 0.5);
@@ -48,46 +48,52 @@ impl AppState for State {
         false
     }
 
-    fn update(&mut self, _window: &Window) {}
+    fn update(&mut self, _clock: &FrameClock) {}
 }
 
 struct View {
-    layer_idx: usize,
+    layer: LayerHandle,
 }
 
 impl AppView<State> for View {
     fn init(renderer: &mut Renderer, state: &State) -> Self {
-        let shade = renderer.make_shade(include_str!("shader.wgsl"));
+        let shade = renderer.make_shade(include_str!("shader.wgsl")).unwrap();
         // let form = renderer.make_form_vertices(
         //     &state.vertices,
         //     &vertex_attr_array![0 => Float32x3, 1 => Float32x3],
         // );
-        let form = renderer.make_form_indexed_vertices(
-            &state.vertices,
-            &vertex_attr_array![0 => Float32x3, 1 => Float32x3],
-            &state.indices,
-        );
-        let sketch = renderer.make_sketch(shade, form);
-        let layer = renderer.make_layer(vec![sketch]);
+        let form = renderer
+            .make_form_indexed_vertices(
+                &state.vertices,
+                &vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                &state.indices,
+            )
+            .unwrap();
+        let sketch = renderer
+            .make_sketch(shade, form, SketchOptions::new())
+            .unwrap();
+        let layer = renderer.make_layer(vec![sketch]).unwrap();
         renderer
             .layer_mut(layer)
+            .unwrap()
             .set_clear_color(Some(state.bg_color));
 
-        Self { layer_idx: layer }
+        Self { layer }
     }
 
     fn render(
         &mut self,
         renderer: &mut Renderer,
         _state: &State,
-    ) -> Result<(), wgpu::SurfaceError> {
-        renderer.render_layer(self.layer_idx)
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
+        renderer.render_layer(self.layer)
     }
 
     fn resize(&mut self, _window: &Window) {}
 }
 
 fn main() {
-    let app = pollster::block_on(App::new(WindowBuilder::new()));
+    let app = pollster::block_on(App::new(WindowBuilder::new())).unwrap();
     run::<State, View>(app);
 }
@@ -8,6 +8,17 @@ struct CameraUniform {
     view_proj: Mat4,
 }
 
+impl CameraUniform {
+    fn new(angle: f32, aspect: f32) -> Self {
+        let eye = vec3(angle.sin() * 1.5, 0.5, angle.cos() * 1.5);
+        let view = Mat4::look_at_rh(eye, Vec3::ZERO, Vec3::Y);
+        let proj = Mat4::perspective_rh(45.0_f32.to_radians(), aspect, 0.1, 100.0);
+        Self {
+            view_proj: proj * view,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 struct Vertex {
@@ -18,7 +29,8 @@ struct Vertex {
 struct State {
     bg_color: wgpu::Color,
     vertices: Vec<Vertex>,
-    indices: Vec<i32>,
+    indices: Vec<u16>,
+    camera_angle: f32,
 }
 
 const COLOR: (f32, f32, f32) = (0.5, 0.0, 0.5);
@@ -47,6 +59,7 @@ impl AppState for State {
                 vertex(vec3(0.44147372, 0.2347359, 0.0)),
             ],
             indices: vec![0, 1, 4, 1, 2, 4, 2, 3, 4],
+            camera_angle: 0.0,
         }
     }
 
@@ -54,46 +67,71 @@ impl AppState for State {
         false
     }
 
-    fn update(&mut self, _window: &Window) {}
+    fn update(&mut self, clock: &FrameClock) {
+        self.camera_angle = clock.time() as f32 * 0.6;
+    }
 }
 
 struct View {
-    layer_idx: usize,
+    layer: LayerHandle,
+    camera: UniformHandle,
+}
+
+fn aspect(renderer: &Renderer) -> f32 {
+    let size = renderer.canvas_size();
+    size.width as f32 / size.height as f32
 }
 
 impl AppView<State> for View {
     fn init(renderer: &mut Renderer, state: &State) -> Self {
-        let shade = renderer.make_shade(include_str!("shader.wgsl"));
+        let shade = renderer.make_shade(include_str!("shader.wgsl")).unwrap();
         // let form = renderer.make_form_vertices(
         //     &state.vertices,
         //     &vertex_attr_array![0 => Float32x3, 1 => Float32x3],
         // );
-        let form = renderer.make_form_indexed_vertices(
-            &state.vertices,
-            &vertex_attr_array![0 => Float32x3, 1 => Float32x3],
-            &state.indices,
-        );
-        let sketch = renderer.make_sketch(shade, form);
-        let layer = renderer.make_layer(vec![sketch]);
-        renderer
-            .layer_mut(layer)
-            .set_clear_color(Some(state.bg_color));
-
-        Self { layer_idx: layer }
+        let form = renderer
+            .make_form_indexed_vertices(
+                &state.vertices,
+                &vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                &state.indices,
+            )
+            .unwrap();
+        let camera = renderer
+            .make_uniform(&CameraUniform::new(0.0, aspect(renderer)))
+            .unwrap();
+        let sketch = renderer
+            .make_sketch(
+                shade,
+                form,
+                SketchOptions::new().with_binding(Binding::uniform(0, 0, camera)),
+            )
+            .unwrap();
+        let layer = renderer.make_layer(vec![sketch]).unwrap();
+        let layer_mut = renderer.layer_mut(layer).unwrap();
+        layer_mut.set_clear_color(Some(state.bg_color));
+        layer_mut.set_depth(Some(1.0));
+        layer_mut.set_sample_count(4);
+
+        Self { layer, camera }
     }
 
     fn render(
         &mut self,
         renderer: &mut Renderer,
-        _state: &State,
-    ) -> Result<(), wgpu::SurfaceError> {
-        renderer.render_layer(self.layer_idx)
+        state: &State,
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
+        // Swing the camera back and forth, as the back faces are culled
+        let mut camera = CameraUniform::new(state.camera_angle.sin() * 0.8, aspect(renderer));
+        camera.view_proj = renderer.tile().clip_transform() * camera.view_proj;
+        renderer.update_uniform(self.camera, &camera)?;
+        renderer.render_layer(self.layer)
     }
 
     fn resize(&mut self, _window: &Window) {}
 }
 
 fn main() {
-    let app = pollster::block_on(App::new(WindowBuilder::new()));
+    let app = pollster::block_on(App::new(WindowBuilder::new())).unwrap();
     run::<State, View>(app);
 }
@@ -0,0 +1,258 @@
+use std::time::Instant;
+
+// Time of the current frame, advanced by `tick` once per frame before
+// `AppState::update`. Realtime clocks follow the wall clock, deterministic
+// clocks advance by the same amount every frame, e.g. for exports.
+#[derive(Debug, Clone)]
+pub struct FrameClock {
+    time: f64,
+    delta: f64,
+    frame: u64,
+    ticked: bool,
+    scale: f64,
+    paused: bool,
+    source: ClockSource,
+    fixed_step: Option<FixedStep>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClockSource {
+    Realtime { last: Option<Instant> },
+    Deterministic { step: f64 },
+}
+
+// Splits frame time into equal simulation steps. Steps that fall behind by
+// more than `max_steps` in one frame are dropped, so a slow frame does not
+// make the next one slower.
+#[derive(Debug, Clone, Copy)]
+struct FixedStep {
+    step: f64,
+    max_steps: u32,
+    accumulator: f64,
+    steps: u32,
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        Self {
+            time: 0.0,
+            delta: 0.0,
+            frame: 0,
+            ticked: false,
+            scale: 1.0,
+            paused: false,
+            source: ClockSource::Realtime { last: None },
+            fixed_step: None,
+        }
+    }
+
+    // Advances exactly 1 / fps seconds per frame, however long frames take
+    pub fn deterministic(mut self, fps: f64) -> Self {
+        self.source = ClockSource::Deterministic { step: 1.0 / fps };
+        self
+    }
+
+    pub fn with_fixed_step(mut self, step: f64, max_steps: u32) -> Self {
+        self.fixed_step = Some(FixedStep {
+            step,
+            max_steps,
+            accumulator: 0.0,
+            steps: 0,
+        });
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    // Time of the first frame
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    // Starts the next frame. The first frame has a delta of 0.
+    pub fn tick(&mut self) {
+        let elapsed = match &mut self.source {
+            ClockSource::Realtime { last } => {
+                let now = Instant::now();
+                let elapsed = last.map_or(0.0, |last| (now - last).as_secs_f64());
+                *last = Some(now);
+                elapsed
+            }
+            ClockSource::Deterministic { step } if self.ticked => *step,
+            ClockSource::Deterministic { .. } => 0.0,
+        };
+        if self.ticked {
+            self.frame += 1;
+        }
+        self.ticked = true;
+
+        self.delta = if self.paused {
+            0.0
+        } else {
+            elapsed * self.scale
+        };
+        self.time += self.delta;
+        if let Some(fixed) = &mut self.fixed_step {
+            fixed.accumulator += self.delta;
+            let steps = (fixed.accumulator / fixed.step) as u32;
+            if steps > fixed.max_steps {
+                fixed.steps = fixed.max_steps;
+                fixed.accumulator %= fixed.step;
+            } else {
+                fixed.steps = steps;
+                fixed.accumulator -= steps as f64 * fixed.step;
+            }
+        }
+    }
+
+    // Seconds since the first frame, scaled and without paused time
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    // Seconds since the last frame, scaled, 0 while paused
+    pub fn delta(&self) -> f64 {
+        self.delta
+    }
+
+    // Index of the current frame, starting at 0. Counts paused frames too.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Number of simulation steps to run this frame, each `step` seconds
+    // long. Without a fixed step, a single step of the frame delta.
+    pub fn steps(&self) -> u32 {
+        self.fixed_step.map_or(1, |fixed| fixed.steps)
+    }
+
+    pub fn step(&self) -> f64 {
+        self.fixed_step.map_or(self.delta, |fixed| fixed.step)
+    }
+
+    // How far the frame is into the next fixed step, from 0 to 1, for
+    // interpolating between the last two simulation states
+    pub fn step_alpha(&self) -> f64 {
+        self.fixed_step
+            .map_or(0.0, |fixed| fixed.accumulator / fixed.step)
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: f64) -> &Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) -> &Self {
+        self.paused = paused;
+        self
+    }
+
+    pub fn toggle_paused(&mut self) -> &Self {
+        self.paused = !self.paused;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_ticks() {
+        let mut clock = FrameClock::new().deterministic(4.0).with_time(1.0);
+        clock.tick();
+        assert_eq!((clock.time(), clock.delta(), clock.frame()), (1.0, 0.0, 0));
+        clock.tick();
+        assert_eq!(
+            (clock.time(), clock.delta(), clock.frame()),
+            (1.25, 0.25, 1)
+        );
+        clock.tick();
+        assert_eq!((clock.time(), clock.delta(), clock.frame()), (1.5, 0.25, 2));
+    }
+
+    #[test]
+    fn paused_frames_count_without_time_passing() {
+        let mut clock = FrameClock::new().deterministic(4.0);
+        clock.tick();
+        clock.tick();
+        clock.toggle_paused();
+        clock.tick();
+        assert_eq!((clock.time(), clock.delta(), clock.frame()), (0.25, 0.0, 2));
+        clock.set_paused(false);
+        clock.tick();
+        assert_eq!((clock.time(), clock.delta(), clock.frame()), (0.5, 0.25, 3));
+    }
+
+    #[test]
+    fn scale_multiplies_delta() {
+        let mut clock = FrameClock::new().deterministic(4.0).with_scale(2.0);
+        clock.tick();
+        clock.tick();
+        assert_eq!((clock.time(), clock.delta()), (0.5, 0.5));
+        clock.set_scale(0.5);
+        clock.tick();
+        assert_eq!((clock.time(), clock.delta()), (0.625, 0.125));
+    }
+
+    #[test]
+    fn without_fixed_step_one_step_of_the_delta() {
+        let mut clock = FrameClock::new().deterministic(4.0);
+        clock.tick();
+        clock.tick();
+        assert_eq!(
+            (clock.steps(), clock.step(), clock.step_alpha()),
+            (1, 0.25, 0.0)
+        );
+    }
+
+    #[test]
+    fn fixed_steps_accumulate() {
+        let mut clock = FrameClock::new()
+            .deterministic(8.0)
+            .with_fixed_step(0.25, 4);
+        clock.tick();
+        assert_eq!((clock.steps(), clock.step_alpha()), (0, 0.0));
+        clock.tick();
+        assert_eq!((clock.steps(), clock.step_alpha()), (0, 0.5));
+        clock.tick();
+        assert_eq!((clock.steps(), clock.step_alpha()), (1, 0.0));
+        assert_eq!(clock.step(), 0.25);
+    }
+
+    #[test]
+    fn fixed_steps_are_clamped_to_max_steps() {
+        // 2.125 seconds per frame, 8.5 steps of which 4 are run
+        let mut clock = FrameClock::new()
+            .deterministic(8.0)
+            .with_scale(17.0)
+            .with_fixed_step(0.25, 4);
+        clock.tick();
+        clock.tick();
+        assert_eq!(clock.delta(), 2.125);
+        assert_eq!(clock.steps(), 4);
+        // The dropped steps are not carried into the next frame
+        assert_eq!(clock.step_alpha(), 0.5);
+        clock.set_scale(1.0);
+        clock.tick();
+        assert_eq!((clock.steps(), clock.step_alpha()), (1, 0.0));
+    }
+}
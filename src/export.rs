@@ -0,0 +1,260 @@
+use std::{fs::File, io::BufWriter, io::Write, path::PathBuf};
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    app::{AppState, AppView},
+    clock::FrameClock,
+    renderer::prelude::{Renderer, RendererError, Tile},
+};
+
+// Renders an AppState/AppView pair headlessly into numbered PNG files,
+// `frame_00001.png` and so on. Time advances by exactly 1 / fps per frame,
+// no matter how long rendering takes, so exports are reproducible.
+pub struct Export {
+    pub dir: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f64,
+    pub frames: u32,
+    // Made deterministic at the export fps when running
+    pub clock: FrameClock,
+}
+
+impl Export {
+    pub fn new(dir: impl Into<PathBuf>, width: u32, height: u32) -> Self {
+        Self {
+            dir: dir.into(),
+            width,
+            height,
+            fps: 60.0,
+            frames: 1,
+            clock: FrameClock::new(),
+        }
+    }
+
+    pub fn with_fps(mut self, fps: f64) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    pub fn with_frames(mut self, frames: u32) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    // For the fixed step or time scale the painting runs with
+    pub fn with_clock(mut self, clock: FrameClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Sets the number of frames to cover the duration at the current fps
+    pub fn with_duration(mut self, seconds: f64) -> Self {
+        self.frames = (seconds * self.fps).round() as u32;
+        self
+    }
+
+    pub fn frame_path(&self, frame: u32) -> PathBuf {
+        self.dir.join(format!("frame_{:05}.png", frame + 1))
+    }
+
+    pub fn run<S: AppState, V: AppView<S>>(&self) -> Result<(), RendererError> {
+        std::fs::create_dir_all(&self.dir).map_err(|error| RendererError::Io {
+            path: self.dir.clone(),
+            error,
+        })?;
+        let mut renderer = pollster::block_on(Renderer::new_headless(self.width, self.height))?;
+        let mut state = S::init();
+        let mut view = V::init(&mut renderer, &state);
+        let mut clock = self.clock.clone().deterministic(self.fps);
+        for frame in 0..self.frames {
+            clock.tick();
+            renderer.set_time(clock.time(), clock.delta(), clock.frame());
+            state.update(&clock);
+            view.render(&mut renderer, &state, &clock)?;
+            renderer.end_frame();
+            let path = self.frame_path(frame);
+            renderer
+                .read_image()?
+                .save(&path)
+                .map_err(|error| RendererError::Image { path, error })?;
+            log::info!("exported frame {} of {}", frame + 1, self.frames);
+        }
+        Ok(())
+    }
+}
+
+// Renders one frame of an AppState/AppView pair in tiles and stitches them
+// into a single PNG, for prints larger than the maximum texture size. Rows
+// of tiles are written as they are finished, so the whole image is never
+// held in memory.
+//
+// The view must place its sketches with the tile: multiply projections by
+// `renderer.tile().clip_transform()` and take aspect ratios from
+// `renderer.canvas_size()`. Views that don't draw the whole canvas into
+// every tile, as nothing applies the transform for them.
+pub struct TiledExport {
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    // Pixels rendered around each tile and cropped again, so effects
+    // sampling neighbouring pixels match across tile edges
+    pub overlap: u32,
+    pub time: f64,
+}
+
+impl TiledExport {
+    pub fn new(path: impl Into<PathBuf>, width: u32, height: u32) -> Self {
+        Self {
+            path: path.into(),
+            width,
+            height,
+            tile_size: 2048,
+            overlap: 0,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    pub fn with_overlap(mut self, overlap: u32) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    // Time passed to the state before rendering
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    // The tile rendered for the part of the canvas at the position, grown
+    // by the overlap within the canvas
+    fn tile(&self, x: u32, y: u32, size: PhysicalSize<u32>) -> Tile {
+        let left = x.saturating_sub(self.overlap);
+        let top = y.saturating_sub(self.overlap);
+        let right = (x + size.width + self.overlap).min(self.width);
+        let bottom = (y + size.height + self.overlap).min(self.height);
+        Tile {
+            offset: PhysicalPosition::new(left, top),
+            size: PhysicalSize::new(right - left, bottom - top),
+            canvas_size: PhysicalSize::new(self.width, self.height),
+        }
+    }
+
+    pub fn run<S: AppState, V: AppView<S>>(&self) -> Result<(), RendererError> {
+        let io_error = |error| RendererError::Io {
+            path: self.path.clone(),
+            error,
+        };
+
+        // Checked before creating the renderer, which fails on textures
+        // larger than the device limits
+        let size = self
+            .tile_size
+            .saturating_add(self.overlap.saturating_mul(2));
+        let max = wgpu::Limits::default().max_texture_dimension_2d;
+        if self.tile_size == 0 {
+            return Err(RendererError::TileSize { size: 0, max });
+        }
+        if size > max {
+            return Err(RendererError::TileSize { size, max });
+        }
+        let first = self.tile(0, 0, PhysicalSize::new(self.tile_size, self.tile_size));
+        let mut renderer =
+            pollster::block_on(Renderer::new_headless(first.size.width, first.size.height))?;
+        let mut state = S::init();
+        let mut view = V::init(&mut renderer, &state);
+        let mut clock = FrameClock::new().deterministic(1.0).with_time(self.time);
+        clock.tick();
+        renderer.set_time(clock.time(), clock.delta(), clock.frame());
+        state.update(&clock);
+
+        let file = File::create(&self.path).map_err(io_error)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| io_error(e.into()))?;
+        let mut stream = writer.stream_writer();
+
+        let row_bytes = self.width as usize * 4;
+        for y in (0..self.height).step_by(self.tile_size as usize) {
+            let rows = self.tile_size.min(self.height - y);
+            let mut strip = vec![0; row_bytes * rows as usize];
+            for x in (0..self.width).step_by(self.tile_size as usize) {
+                let columns = self.tile_size.min(self.width - x);
+                let tile = self.tile(x, y, PhysicalSize::new(columns, rows));
+                renderer.set_tile(tile);
+                view.render(&mut renderer, &state, &clock)?;
+                let image = renderer.read_image()?;
+
+                // Copies the tile without its overlap into the strip
+                let left = (x - tile.offset.x) as usize;
+                let top = (y - tile.offset.y) as usize;
+                let tile_bytes = columns as usize * 4;
+                for row in 0..rows as usize {
+                    let src = ((top + row) * image.width() as usize + left) * 4;
+                    let dst = row * row_bytes + x as usize * 4;
+                    strip[dst..dst + tile_bytes]
+                        .copy_from_slice(&image.as_raw()[src..src + tile_bytes]);
+                }
+            }
+            stream.write_all(&strip).map_err(io_error)?;
+            log::info!("exported rows {} to {} of {}", y, y + rows, self.height);
+        }
+        stream.finish().map_err(|e| io_error(e.into()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(export: &TiledExport, x: u32, y: u32, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let tile = export.tile(x, y, PhysicalSize::new(width, height));
+        assert_eq!(
+            tile.canvas_size,
+            PhysicalSize::new(export.width, export.height)
+        );
+        (
+            tile.offset.x,
+            tile.offset.y,
+            tile.size.width,
+            tile.size.height,
+        )
+    }
+
+    #[test]
+    fn tiles_without_overlap() {
+        let export = TiledExport::new("print.png", 250, 100).with_tile_size(100);
+        assert_eq!(tile(&export, 0, 0, 100, 100), (0, 0, 100, 100));
+        assert_eq!(tile(&export, 200, 0, 50, 100), (200, 0, 50, 100));
+    }
+
+    #[test]
+    fn overlap_is_clamped_at_the_canvas_edges() {
+        let export = TiledExport::new("print.png", 250, 200)
+            .with_tile_size(100)
+            .with_overlap(8);
+        // Top left corner only grows right and down
+        assert_eq!(tile(&export, 0, 0, 100, 100), (0, 0, 108, 108));
+        // Inner edges grow both ways
+        assert_eq!(tile(&export, 100, 100, 100, 100), (92, 92, 116, 108));
+        // The last, narrower column only grows left
+        assert_eq!(tile(&export, 200, 0, 50, 100), (192, 0, 58, 108));
+    }
+
+    #[test]
+    fn overlap_larger_than_the_canvas_covers_it() {
+        let export = TiledExport::new("print.png", 30, 20)
+            .with_tile_size(10)
+            .with_overlap(100);
+        assert_eq!(tile(&export, 10, 10, 10, 10), (0, 0, 30, 20));
+    }
+}
@@ -1,8 +1,13 @@
 pub mod app;
+pub mod clock;
+pub mod export;
 pub mod renderer;
+pub mod snapshot;
 
 pub mod prelude {
     pub use super::app::*;
+    pub use super::clock::*;
+    pub use super::export::*;
     pub use super::renderer::prelude::*;
     pub use glam::*;
 }
@@ -21,20 +21,21 @@ impl AppState for State {
         false
     }
 
-    fn update(&mut self, _window: &Window) {}
+    fn update(&mut self, _clock: &FrameClock) {}
 }
 
 struct View {
-    layer_idx: usize,
+    layer: LayerHandle,
 }
 
 impl AppView<State> for View {
     fn init(renderer: &mut Renderer, state: &State) -> Self {
-        let layer_idx = renderer.make_layer(vec![]);
+        let layer = renderer.make_layer(vec![]).unwrap();
         renderer
-            .layer_mut(layer_idx)
+            .layer_mut(layer)
+            .unwrap()
             .set_clear_color(Some(state.color));
-        Self { layer_idx }
+        Self { layer }
     }
 
     fn resize(&mut self, _window: &Window) {}
@@ -43,12 +44,13 @@ impl AppView<State> for View {
         &mut self,
         renderer: &mut Renderer,
         _state: &State,
-    ) -> Result<(), wgpu::SurfaceError> {
-        renderer.render_layer(self.layer_idx)
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
+        renderer.render_layer(self.layer)
     }
 }
 
 fn main() {
-    let app = pollster::block_on(App::new(WindowBuilder::new()));
+    let app = pollster::block_on(App::new(WindowBuilder::new())).unwrap();
     run::<State, View>(app);
 }
@@ -0,0 +1,274 @@
+use std::{fmt, hash::Hash, marker::PhantomData};
+
+// Typed reference into an `Arena<T>`. The generation detects handles whose
+// slot was freed and reused by another value.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    _marker: PhantomData<fn() -> T>,
+}
+
+pub type FormHandle = Handle<super::Form>;
+pub type ShadeHandle = Handle<super::Shade>;
+pub type SketchHandle = Handle<super::Sketch>;
+pub type LayerHandle = Handle<super::Layer>;
+pub type UniformHandle = Handle<super::Uniform>;
+pub type TextureHandle = Handle<super::Texture>;
+pub type SamplerHandle = Handle<super::Sampler>;
+pub type FeedbackHandle = Handle<super::Feedback>;
+pub type StorageBufferHandle = Handle<super::StorageBuffer>;
+pub type ComputeHandle = Handle<super::Compute>;
+
+impl<T> Handle<T> {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // For tests needing handles of resources that require a device
+    #[cfg(test)]
+    pub(crate) fn from_raw_parts(index: u32, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+            _marker: PhantomData,
+        }
+    }
+}
+
+// Implemented by hand, deriving would require the same traits on T
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = std::any::type_name::<T>().rsplit("::").next().unwrap_or("");
+        write!(f, "{}Handle({}v{})", name, self.index, self.generation)
+    }
+}
+
+enum Entry<T> {
+    Occupied { generation: u32, value: T },
+    Free { generation: u32 },
+}
+
+pub struct Arena<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![],
+            free: vec![],
+        }
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        match self.free.pop() {
+            Some(index) => {
+                let entry = &mut self.entries[index as usize];
+                let generation = match entry {
+                    Entry::Free { generation } => *generation,
+                    Entry::Occupied { .. } => unreachable!("occupied entry in free list"),
+                };
+                *entry = Entry::Occupied { generation, value };
+                Handle {
+                    index,
+                    generation,
+                    _marker: PhantomData,
+                }
+            }
+            None => {
+                let index = self.entries.len() as u32;
+                self.entries.push(Entry::Occupied {
+                    generation: 0,
+                    value,
+                });
+                Handle {
+                    index,
+                    generation: 0,
+                    _marker: PhantomData,
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        match self.entries.get(handle.index as usize) {
+            Some(Entry::Occupied { generation, value }) if *generation == handle.generation => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        match self.entries.get_mut(handle.index as usize) {
+            Some(Entry::Occupied { generation, value }) if *generation == handle.generation => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    // Frees the slot of the handle. All copies of the handle become stale.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        if !self.contains(handle) {
+            return None;
+        }
+        let entry = std::mem::replace(
+            &mut self.entries[handle.index as usize],
+            Entry::Free {
+                generation: handle.generation.wrapping_add(1),
+            },
+        );
+        self.free.push(handle.index);
+        match entry {
+            Entry::Occupied { value, .. } => Some(value),
+            Entry::Free { .. } => unreachable!(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| match entry {
+                Entry::Occupied { generation, value } => Some((
+                    Handle {
+                        index: index as u32,
+                        generation: *generation,
+                        _marker: PhantomData,
+                    },
+                    value,
+                )),
+                Entry::Free { .. } => None,
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T>, &mut T)> {
+        self.entries
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, entry)| match entry {
+                Entry::Occupied { generation, value } => Some((
+                    Handle {
+                        index: index as u32,
+                        generation: *generation,
+                        _marker: PhantomData,
+                    },
+                    value,
+                )),
+                Entry::Free { .. } => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_get() {
+        let mut arena = Arena::new();
+        let a = arena.insert("a");
+        let b = arena.insert("b");
+        assert_ne!(a, b);
+        assert_eq!(arena.get(a), Some(&"a"));
+        assert_eq!(arena.get(b), Some(&"b"));
+        assert_eq!(arena.len(), 2);
+    }
+
+    #[test]
+    fn removed_handles_are_stale() {
+        let mut arena = Arena::new();
+        let a = arena.insert(1);
+        assert_eq!(arena.remove(a), Some(1));
+        assert!(!arena.contains(a));
+        assert_eq!(arena.get(a), None);
+        assert_eq!(arena.get_mut(a), None);
+        assert_eq!(arena.remove(a), None);
+        assert!(arena.is_empty());
+    }
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut arena = Arena::new();
+        let a = arena.insert(1);
+        arena.remove(a);
+        let b = arena.insert(2);
+        assert_eq!(b.index(), a.index());
+        assert_eq!(b.generation(), a.generation() + 1);
+        // The stale handle does not see the value now in its slot
+        assert_eq!(arena.get(a), None);
+        assert_eq!(arena.remove(a), None);
+        assert_eq!(arena.get(b), Some(&2));
+
+        arena.remove(b);
+        let c = arena.insert(3);
+        assert_eq!(c.generation(), a.generation() + 2);
+        assert_eq!(arena.get(b), None);
+        assert_eq!(arena.get(c), Some(&3));
+    }
+
+    #[test]
+    fn iter_skips_free_slots() {
+        let mut arena = Arena::new();
+        let a = arena.insert('a');
+        let b = arena.insert('b');
+        let c = arena.insert('c');
+        arena.remove(b);
+        let items: Vec<_> = arena.iter().map(|(h, v)| (h, *v)).collect();
+        assert_eq!(items, vec![(a, 'a'), (c, 'c')]);
+
+        for (_, value) in arena.iter_mut() {
+            *value = value.to_ascii_uppercase();
+        }
+        assert_eq!(arena.get(a), Some(&'A'));
+        assert_eq!(arena.get(c), Some(&'C'));
+        assert_eq!(arena.len(), 2);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::prelude::*;
+
+// How many workgroups a compute dispatches. Sizes other than `Workgroups`
+// count invocations and are divided by the workgroup size of the shade,
+// rounding up, so shades should skip invocations past the end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dispatch {
+    Workgroups(u32, u32, u32),
+    Invocations(u32, u32, u32),
+    // One invocation per texel, e.g. of the storage texture written
+    Texture(TextureHandle),
+    // One invocation per element of `stride` bytes
+    StorageBuffer {
+        buffer: StorageBufferHandle,
+        stride: u64,
+    },
+}
+
+impl Dispatch {
+    // Whether the dispatch size is taken from the resource
+    pub fn uses(&self, resource: BindingResource) -> bool {
+        match (*self, resource) {
+            (
+                Dispatch::Texture(texture),
+                BindingResource::Texture(other) | BindingResource::StorageTexture(other),
+            ) => texture == other,
+            (Dispatch::StorageBuffer { buffer, .. }, BindingResource::StorageBuffer(other)) => {
+                buffer == other
+            }
+            _ => false,
+        }
+    }
+}
+
+// A compute shade with its bindings, dispatched in frames before or between
+// layers, e.g. to step a simulation that later layers draw
+pub struct Compute {
+    pub shade: ShadeHandle,
+    pub bindings: Vec<Binding>,
+    pub dispatch: Dispatch,
+    pub pipeline: wgpu::ComputePipeline,
+    pub bind_groups: Vec<wgpu::BindGroup>,
+    pub swapped_bind_groups: Option<Vec<wgpu::BindGroup>>,
+}
+
+impl Compute {
+    pub fn new(
+        renderer: &Renderer,
+        shade_handle: ShadeHandle,
+        bindings: Vec<Binding>,
+        dispatch: Dispatch,
+    ) -> Result<Self, RendererError> {
+        let shade = renderer.shade(shade_handle)?;
+        if shade.stage != ShadeStage::Compute {
+            return Err(RendererError::ShadeStage {
+                expected: ShadeStage::Compute,
+                actual: shade.stage,
+            });
+        }
+
+        check_bindings(shade, &bindings)?;
+        let bind_group_layouts = create_bind_group_layouts(renderer, shade, &bindings)?;
+        let bind_groups = create_bind_groups(renderer, &bindings, &bind_group_layouts, false)?;
+        let swapped_bind_groups =
+            create_swapped_bind_groups(renderer, &bindings, &bind_group_layouts)?;
+
+        let pipeline_layout =
+            renderer
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Compute Pipeline Layout"),
+                    bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
+                    push_constant_ranges: &[],
+                });
+        let pipeline = renderer
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Compute Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shade.shader,
+                entry_point: "cs_main",
+            });
+
+        Ok(Self {
+            shade: shade_handle,
+            bindings,
+            dispatch,
+            pipeline,
+            bind_groups,
+            swapped_bind_groups,
+        })
+    }
+
+    // Number of workgroups in x, y and z for the current dispatch size
+    pub fn workgroups(&self, renderer: &Renderer) -> Result<[u32; 3], RendererError> {
+        let invocations = match self.dispatch {
+            Dispatch::Workgroups(x, y, z) => return Ok([x, y, z]),
+            Dispatch::Invocations(x, y, z) => [x, y, z],
+            Dispatch::Texture(handle) => {
+                let size = renderer.texture(handle)?.size;
+                [size.width, size.height, 1]
+            }
+            Dispatch::StorageBuffer { buffer, stride } => {
+                let size = renderer.storage_buffer(buffer)?.size;
+                [(size / stride.max(1)) as u32, 1, 1]
+            }
+        };
+        let workgroup_size = renderer.shade(self.shade)?.workgroup_size;
+        Ok([0, 1, 2].map(|i| invocations[i].div_ceil(workgroup_size[i])))
+    }
+
+    // The bind groups to dispatch with, depending on the renderer's feedback sides
+    pub fn bind_groups(&self, swapped: bool) -> &[wgpu::BindGroup] {
+        match (&self.swapped_bind_groups, swapped) {
+            (Some(bind_groups), true) => bind_groups,
+            _ => &self.bind_groups,
+        }
+    }
+
+    // Bound or sized by the resource, so it cannot be removed
+    pub fn uses(&self, resource: BindingResource) -> bool {
+        self.dispatch.uses(resource) || self.bindings.iter().any(|b| b.resource == resource)
+    }
+
+    // Records a compute pass dispatching the shade once
+    pub fn encode(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<(), RendererError> {
+        let [x, y, z] = self.workgroups(renderer)?;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        let bind_groups = self.bind_groups(renderer.feedback_swapped());
+        for (group, bind_group) in bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(group as u32, bind_group, &[]);
+        }
+        compute_pass.dispatch(x, y, z);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatches_use_the_resource_they_are_sized_by() {
+        let texture = TextureHandle::from_raw_parts(0, 0);
+        let other_texture = TextureHandle::from_raw_parts(0, 1);
+        let buffer = StorageBufferHandle::from_raw_parts(2, 0);
+
+        let by_texture = Dispatch::Texture(texture);
+        assert!(by_texture.uses(BindingResource::Texture(texture)));
+        assert!(by_texture.uses(BindingResource::StorageTexture(texture)));
+        assert!(!by_texture.uses(BindingResource::Texture(other_texture)));
+        assert!(!by_texture.uses(BindingResource::StorageBuffer(buffer)));
+
+        let by_buffer = Dispatch::StorageBuffer { buffer, stride: 16 };
+        assert!(by_buffer.uses(BindingResource::StorageBuffer(buffer)));
+        assert!(!by_buffer.uses(BindingResource::Texture(texture)));
+
+        let fixed = Dispatch::Invocations(64, 1, 1);
+        assert!(!fixed.uses(BindingResource::Texture(texture)));
+        assert!(!fixed.uses(BindingResource::StorageBuffer(buffer)));
+    }
+}
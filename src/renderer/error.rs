@@ -0,0 +1,302 @@
+use std::{fmt, path::PathBuf};
+
+use super::{Handle, ShadeError, ShadeStage};
+
+#[derive(Debug)]
+pub enum RendererError {
+    // No adapter matched the requested options
+    AdapterNotFound {
+        backends: wgpu::Backends,
+        power_preference: wgpu::PowerPreference,
+        force_fallback_adapter: bool,
+    },
+    RequestDevice(wgpu::RequestDeviceError),
+    // The adapter does not support presenting to the window surface
+    IncompatibleSurface,
+    WindowCreation(winit::error::OsError),
+    Surface(wgpu::SurfaceError),
+    Shade(ShadeError),
+    // Sketches need render shades, computes need compute shades
+    ShadeStage {
+        expected: ShadeStage,
+        actual: ShadeStage,
+    },
+    // The handle was never valid or its resource has been removed
+    InvalidHandle {
+        kind: &'static str,
+        index: usize,
+        generation: u32,
+    },
+    // The resource cannot be removed while other resources depend on it
+    ResourceInUse {
+        resource: String,
+        used_by: String,
+    },
+    // The shade uses a binding the sketch does not provide
+    MissingBinding {
+        group: u32,
+        binding: u32,
+    },
+    DuplicateBinding {
+        group: u32,
+        binding: u32,
+    },
+    // Uniform updates must have the size the uniform was created with
+    UniformSize {
+        expected: u64,
+        actual: u64,
+    },
+    // Storage buffer updates must cover the whole buffer
+    StorageSize {
+        expected: u64,
+        actual: u64,
+    },
+    // Texture updates must cover the whole texture
+    TextureSize {
+        expected: u64,
+        actual: u64,
+    },
+    // Vertex and instance updates must use the type the form was created with
+    VertexStride {
+        expected: u64,
+        actual: u64,
+    },
+    // Index updates must use the index type the form was created with
+    IndexFormat {
+        expected: wgpu::IndexFormat,
+        actual: wgpu::IndexFormat,
+    },
+    // An index refers past the last vertex
+    IndexOutOfBounds {
+        index: u32,
+        vertex_count: u32,
+    },
+    // The form has no buffer of the kind being updated
+    MissingFormBuffer(&'static str),
+    // Instanced forms take a plain mesh, not another instanced form
+    NestedInstancing,
+    // The device lacks a feature the requested state needs
+    UnsupportedFeature(wgpu::Features),
+    // Layer sample counts must be powers of two
+    InvalidSampleCount(u32),
+    // Layers must be prepared with `Renderer::prepare_layer` before encoding
+    UnpreparedLayer,
+    // Multisampled layers cannot load what earlier layers drew, so they must
+    // clear their target
+    MultisampledLoad(String),
+    // Images can only be written into 8 bit RGBA and BGRA textures
+    TextureFormat(wgpu::TextureFormat),
+    // Layers can only render into textures made with `make_render_target`
+    NotRenderTarget(String),
+    // Storage texture bindings need textures made with `make_storage_texture`
+    NotStorage(String),
+    // A sketch of the layer samples the texture the layer renders into
+    TargetSampled {
+        target: String,
+        sketch: String,
+    },
+    // Tiles must not be empty and, including their overlap, fit into a texture
+    TileSize {
+        size: u32,
+        max: u32,
+    },
+    // Headless renderers need a target of at least one pixel
+    EmptySize {
+        width: u32,
+        height: u32,
+    },
+    // Image readback is only available for headless renderers
+    NotHeadless,
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Image {
+        path: PathBuf,
+        error: image::ImageError,
+    },
+    // Error reported by wgpu's own validation, e.g. a vertex layout that
+    // does not match the shader inputs
+    Gpu(String),
+}
+
+impl RendererError {
+    pub fn invalid_handle<T>(handle: Handle<T>) -> Self {
+        let kind = std::any::type_name::<T>()
+            .rsplit("::")
+            .next()
+            .unwrap_or("resource");
+        RendererError::InvalidHandle {
+            kind,
+            index: handle.index(),
+            generation: handle.generation(),
+        }
+    }
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RendererError::AdapterNotFound {
+                backends,
+                power_preference,
+                force_fallback_adapter,
+            } => write!(
+                f,
+                "no graphics adapter found (backends: {:?}, power preference: {:?}, fallback adapter: {})",
+                backends, power_preference, force_fallback_adapter
+            ),
+            RendererError::RequestDevice(e) => write!(f, "failed to request device: {}", e),
+            RendererError::IncompatibleSurface => {
+                write!(f, "the adapter cannot present to the window surface")
+            }
+            RendererError::WindowCreation(e) => write!(f, "failed to create window: {}", e),
+            RendererError::Surface(e) => write!(f, "surface error: {}", e),
+            RendererError::Shade(e) => write!(f, "{}", e),
+            RendererError::ShadeStage { expected, actual } => write!(
+                f,
+                "expected a {:?} shade, but the shade is a {:?} shade",
+                expected, actual
+            ),
+            RendererError::InvalidHandle {
+                kind,
+                index,
+                generation,
+            } => write!(
+                f,
+                "invalid or stale {} handle (index {}, generation {})",
+                kind, index, generation
+            ),
+            RendererError::ResourceInUse { resource, used_by } => {
+                write!(f, "{} is still used by {}", resource, used_by)
+            }
+            RendererError::MissingBinding { group, binding } => write!(
+                f,
+                "the shade uses group {} binding {}, but the sketch does not bind it",
+                group, binding
+            ),
+            RendererError::DuplicateBinding { group, binding } => {
+                write!(f, "group {} binding {} is bound twice", group, binding)
+            }
+            RendererError::UniformSize { expected, actual } => write!(
+                f,
+                "uniform data has {} bytes, but the uniform was created with {} bytes",
+                actual, expected
+            ),
+            RendererError::StorageSize { expected, actual } => write!(
+                f,
+                "storage data has {} bytes, but the buffer holds {} bytes",
+                actual, expected
+            ),
+            RendererError::TextureSize { expected, actual } => write!(
+                f,
+                "texture data has {} bytes, but the texture holds {} bytes",
+                actual, expected
+            ),
+            RendererError::VertexStride { expected, actual } => write!(
+                f,
+                "vertex data has a stride of {} bytes, but the form was created with {} bytes",
+                actual, expected
+            ),
+            RendererError::IndexFormat { expected, actual } => write!(
+                f,
+                "index data is {:?}, but the form was created with {:?}",
+                actual, expected
+            ),
+            RendererError::IndexOutOfBounds {
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "index {} is out of bounds for {} vertices",
+                index, vertex_count
+            ),
+            RendererError::MissingFormBuffer(buffer) => {
+                write!(f, "the form has no {} to update", buffer)
+            }
+            RendererError::NestedInstancing => {
+                write!(f, "the mesh of an instanced form cannot be instanced")
+            }
+            RendererError::UnsupportedFeature(features) => {
+                write!(f, "the device does not support {:?}", features)
+            }
+            RendererError::InvalidSampleCount(count) => {
+                write!(f, "{} is not a valid sample count", count)
+            }
+            RendererError::UnpreparedLayer => {
+                write!(f, "layer was not prepared before encoding")
+            }
+            RendererError::MultisampledLoad(layer) => write!(
+                f,
+                "multisampled layer {} needs a clear color, it cannot load its target",
+                layer
+            ),
+            RendererError::TextureFormat(format) => {
+                write!(f, "cannot write an image into a {:?} texture", format)
+            }
+            RendererError::NotRenderTarget(texture) => {
+                write!(f, "{} is not a render target", texture)
+            }
+            RendererError::NotStorage(texture) => {
+                write!(f, "{} is not a storage texture", texture)
+            }
+            RendererError::TargetSampled { target, sketch } => write!(
+                f,
+                "{} samples {}, which its layer renders into",
+                sketch, target
+            ),
+            RendererError::TileSize { size, max } => write!(
+                f,
+                "tiles must be between 1 and {} pixels including overlap, not {}",
+                max, size
+            ),
+            RendererError::EmptySize { width, height } => {
+                write!(f, "cannot render into an empty {}x{} target", width, height)
+            }
+            RendererError::NotHeadless => {
+                write!(f, "image readback requires a headless renderer")
+            }
+            RendererError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            RendererError::Image { path, error } => write!(f, "{}: {}", path.display(), error),
+            RendererError::Gpu(message) => write!(f, "gpu error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::RequestDevice(e) => Some(e),
+            RendererError::WindowCreation(e) => Some(e),
+            RendererError::Surface(e) => Some(e),
+            RendererError::Shade(e) => Some(e),
+            RendererError::Io { error, .. } => Some(error),
+            RendererError::Image { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RendererError {
+    fn from(e: wgpu::RequestDeviceError) -> Self {
+        RendererError::RequestDevice(e)
+    }
+}
+
+impl From<wgpu::SurfaceError> for RendererError {
+    fn from(e: wgpu::SurfaceError) -> Self {
+        RendererError::Surface(e)
+    }
+}
+
+impl From<ShadeError> for RendererError {
+    fn from(e: ShadeError) -> Self {
+        RendererError::Shade(e)
+    }
+}
+
+impl From<winit::error::OsError> for RendererError {
+    fn from(e: winit::error::OsError) -> Self {
+        RendererError::WindowCreation(e)
+    }
+}
@@ -0,0 +1,25 @@
+use super::TextureHandle;
+
+// A pair of render targets for reading the previous frame while rendering
+// the next one. The renderer swaps the sides of all feedbacks at the end of
+// every frame, whether or not a layer rendered into them, so feedbacks
+// should be rendered into every frame.
+pub struct Feedback {
+    pub targets: [TextureHandle; 2],
+}
+
+impl Feedback {
+    pub fn new(targets: [TextureHandle; 2]) -> Self {
+        Self { targets }
+    }
+
+    // The side holding the last frame, bound to sketches
+    pub fn previous(&self, swapped: bool) -> TextureHandle {
+        self.targets[swapped as usize]
+    }
+
+    // The side layers targeting the feedback render into
+    pub fn next(&self, swapped: bool) -> TextureHandle {
+        self.targets[!swapped as usize]
+    }
+}
@@ -1,10 +1,14 @@
-use crate::prelude::Renderer;
+use std::ops::Range;
+
+use crate::prelude::{Renderer, RendererError};
 use bytemuck::Pod;
-use wgpu::util::DeviceExt;
 
+// Vertex, index and instance buffers are created with COPY_DST, so forms can
+// be updated in place. The capacity is the size of the buffer in bytes.
 pub struct VertexBuffer {
     pub vertices: wgpu::Buffer,
     pub vertex_count: u32,
+    pub vertex_capacity: u64,
     pub array_stride: u64,
     pub attributes: Vec<wgpu::VertexAttribute>,
 }
@@ -15,60 +19,547 @@ impl VertexBuffer {
         vertex_data: &[T],
         attributes: &[wgpu::VertexAttribute],
     ) -> Self {
+        let (vertices, vertex_capacity) = create_buffer(
+            &renderer.device,
+            "Vertex Buffer",
+            bytemuck::cast_slice(vertex_data),
+            wgpu::BufferUsages::VERTEX,
+        );
         Self {
-            vertices: renderer
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("vertex buffer label (TODO)"),
-                    contents: bytemuck::cast_slice(vertex_data),
-                    usage: wgpu::BufferUsages::VERTEX,
-                }),
+            vertices,
             vertex_count: vertex_data.len() as u32,
+            vertex_capacity,
             array_stride: std::mem::size_of::<T>() as wgpu::BufferAddress,
             attributes: attributes.to_vec(),
         }
     }
+
+    pub fn update<T: Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_data: &[T],
+    ) -> Result<(), RendererError> {
+        check_stride::<T>(self.array_stride)?;
+        write_buffer(
+            device,
+            queue,
+            &mut self.vertices,
+            &mut self.vertex_capacity,
+            "Vertex Buffer",
+            bytemuck::cast_slice(vertex_data),
+            wgpu::BufferUsages::VERTEX,
+        );
+        self.vertex_count = vertex_data.len() as u32;
+        Ok(())
+    }
+}
+
+// Index types of indexed forms
+pub trait VertexIndex: Pod + PartialEq {
+    const FORMAT: wgpu::IndexFormat;
+    // Separates strips when drawing strip topologies, where it is exempt
+    // from bounds checks
+    const RESTART: Self;
+
+    fn to_u32(self) -> u32;
+}
+
+impl VertexIndex for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+    const RESTART: Self = u16::MAX;
+
+    fn to_u32(self) -> u32 {
+        self as u32
+    }
 }
+
+impl VertexIndex for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+    const RESTART: Self = u32::MAX;
+
+    fn to_u32(self) -> u32 {
+        self
+    }
+}
+
 pub struct VertexIndexBuffer {
     pub vertices: wgpu::Buffer,
     pub indices: wgpu::Buffer,
     pub vertex_count: u32,
+    pub index_count: u32,
+    pub index_format: wgpu::IndexFormat,
+    // Largest index other than the restart index, checked against the vertex
+    // count whenever either changes
+    pub max_index: Option<u32>,
+    // Set if the indices contain the restart index, which only sketches with
+    // strip topologies can draw
+    pub restart_index: Option<u32>,
+    pub vertex_capacity: u64,
+    pub index_capacity: u64,
     pub array_stride: u64,
     pub attributes: Vec<wgpu::VertexAttribute>,
 }
 
 impl VertexIndexBuffer {
-    pub fn new<T: Pod>(
+    pub fn new<T: Pod, I: VertexIndex>(
         renderer: &Renderer,
         vertex_data: &[T],
         attributes: &[wgpu::VertexAttribute],
-        index_data: &[i32],
+        index_data: &[I],
+    ) -> Result<Self, RendererError> {
+        // The topology is checked when creating sketches drawing the form
+        check_indices(index_data, vertex_data.len() as u32, true)?;
+        let (vertices, vertex_capacity) = create_buffer(
+            &renderer.device,
+            "Vertex Buffer",
+            bytemuck::cast_slice(vertex_data),
+            wgpu::BufferUsages::VERTEX,
+        );
+        let (indices, index_capacity) = create_buffer(
+            &renderer.device,
+            "Index Buffer",
+            bytemuck::cast_slice(index_data),
+            wgpu::BufferUsages::INDEX,
+        );
+        Ok(Self {
+            vertices,
+            indices,
+            vertex_count: vertex_data.len() as u32,
+            index_count: index_data.len() as u32,
+            index_format: I::FORMAT,
+            max_index: max_index(index_data),
+            restart_index: restart_index(index_data),
+            vertex_capacity,
+            index_capacity,
+            array_stride: std::mem::size_of::<T>() as wgpu::BufferAddress,
+            attributes: attributes.to_vec(),
+        })
+    }
+
+    // Updates that remove vertices must update the indices first, so no
+    // index points past the last vertex
+    pub fn update_vertices<T: Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_data: &[T],
+    ) -> Result<(), RendererError> {
+        check_stride::<T>(self.array_stride)?;
+        let vertex_count = vertex_data.len() as u32;
+        if let Some(index) = self.max_index.filter(|index| *index >= vertex_count) {
+            return Err(RendererError::IndexOutOfBounds {
+                index,
+                vertex_count,
+            });
+        }
+        write_buffer(
+            device,
+            queue,
+            &mut self.vertices,
+            &mut self.vertex_capacity,
+            "Vertex Buffer",
+            bytemuck::cast_slice(vertex_data),
+            wgpu::BufferUsages::VERTEX,
+        );
+        self.vertex_count = vertex_data.len() as u32;
+        Ok(())
+    }
+
+    // The index type must match the one the form was created with. The
+    // restart index is only allowed if all sketches drawing the form use
+    // strip topologies.
+    pub fn update_indices<I: VertexIndex>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index_data: &[I],
+        allow_restart: bool,
+    ) -> Result<(), RendererError> {
+        if I::FORMAT != self.index_format {
+            return Err(RendererError::IndexFormat {
+                expected: self.index_format,
+                actual: I::FORMAT,
+            });
+        }
+        check_indices(index_data, self.vertex_count, allow_restart)?;
+        write_buffer(
+            device,
+            queue,
+            &mut self.indices,
+            &mut self.index_capacity,
+            "Index Buffer",
+            bytemuck::cast_slice(index_data),
+            wgpu::BufferUsages::INDEX,
+        );
+        self.index_count = index_data.len() as u32;
+        self.max_index = max_index(index_data);
+        self.restart_index = restart_index(index_data);
+        Ok(())
+    }
+}
+
+// Per-instance attributes, stepped once per drawn instance
+pub struct InstanceBuffer {
+    pub instances: wgpu::Buffer,
+    pub instance_count: u32,
+    pub instance_capacity: u64,
+    pub array_stride: u64,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl InstanceBuffer {
+    pub fn new<T: Pod>(
+        renderer: &Renderer,
+        instance_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
     ) -> Self {
+        let (instances, instance_capacity) = create_buffer(
+            &renderer.device,
+            "Instance Buffer",
+            bytemuck::cast_slice(instance_data),
+            wgpu::BufferUsages::VERTEX,
+        );
         Self {
-            vertices: renderer
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("vertex buffer label (TODO)"),
-                    contents: bytemuck::cast_slice(vertex_data),
-                    usage: wgpu::BufferUsages::VERTEX,
-                }),
-
-            indices: renderer
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Index Buffer"),
-                    contents: bytemuck::cast_slice(index_data),
-                    usage: wgpu::BufferUsages::INDEX,
-                }),
-            vertex_count: index_data.len() as u32,
+            instances,
+            instance_count: instance_data.len() as u32,
+            instance_capacity,
             array_stride: std::mem::size_of::<T>() as wgpu::BufferAddress,
             attributes: attributes.to_vec(),
         }
     }
+
+    pub fn update<T: Pod>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instance_data: &[T],
+    ) -> Result<(), RendererError> {
+        check_stride::<T>(self.array_stride)?;
+        write_buffer(
+            device,
+            queue,
+            &mut self.instances,
+            &mut self.instance_capacity,
+            "Instance Buffer",
+            bytemuck::cast_slice(instance_data),
+            wgpu::BufferUsages::VERTEX,
+        );
+        self.instance_count = instance_data.len() as u32;
+        Ok(())
+    }
 }
 
 pub enum Form {
-    SimpleRange { vertex_count: u32 },
+    SimpleRange {
+        vertex_count: u32,
+    },
     Vertices(VertexBuffer),
     IndexedVertices(VertexIndexBuffer),
+    // Draws the mesh once per instance. The mesh is never instanced itself.
+    Instanced {
+        mesh: Box<Form>,
+        instances: InstanceBuffer,
+    },
+}
+
+impl Form {
+    pub fn simple_range(vertex_count: u32) -> Self {
+        Form::SimpleRange { vertex_count }
+    }
+
+    pub fn vertices<T: Pod>(
+        renderer: &Renderer,
+        vertex_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+    ) -> Self {
+        Form::Vertices(VertexBuffer::new(renderer, vertex_data, attributes))
+    }
+
+    pub fn indexed_vertices<T: Pod, I: VertexIndex>(
+        renderer: &Renderer,
+        vertex_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+        index_data: &[I],
+    ) -> Result<Self, RendererError> {
+        Ok(Form::IndexedVertices(VertexIndexBuffer::new(
+            renderer,
+            vertex_data,
+            attributes,
+            index_data,
+        )?))
+    }
+
+    pub fn is_instanced(&self) -> bool {
+        matches!(self, Form::Instanced { .. })
+    }
+
+    // Layouts of the vertex buffers in slot order: the mesh vertices first,
+    // then the instances
+    pub fn buffer_layouts(&self) -> Vec<wgpu::VertexBufferLayout<'_>> {
+        match self {
+            Form::SimpleRange { .. } => vec![],
+            Form::Vertices(buf) => vec![buffer_layout(
+                buf.array_stride,
+                wgpu::VertexStepMode::Vertex,
+                &buf.attributes,
+            )],
+            Form::IndexedVertices(buf) => vec![buffer_layout(
+                buf.array_stride,
+                wgpu::VertexStepMode::Vertex,
+                &buf.attributes,
+            )],
+            Form::Instanced { mesh, instances } => {
+                let mut layouts = mesh.buffer_layouts();
+                layouts.push(buffer_layout(
+                    instances.array_stride,
+                    wgpu::VertexStepMode::Instance,
+                    &instances.attributes,
+                ));
+                layouts
+            }
+        }
+    }
+
+    pub fn index_format(&self) -> Option<wgpu::IndexFormat> {
+        match self {
+            Form::IndexedVertices(buf) => Some(buf.index_format),
+            Form::Instanced { mesh, .. } => mesh.index_format(),
+            _ => None,
+        }
+    }
+
+    // Indices of the form may contain the restart index, which is out of
+    // bounds for topologies other than strips
+    pub fn check_topology(&self, topology: wgpu::PrimitiveTopology) -> Result<(), RendererError> {
+        match self {
+            Form::IndexedVertices(VertexIndexBuffer {
+                restart_index: Some(index),
+                vertex_count,
+                ..
+            }) if !is_strip(topology) => Err(RendererError::IndexOutOfBounds {
+                index: *index,
+                vertex_count: *vertex_count,
+            }),
+            Form::Instanced { mesh, .. } => mesh.check_topology(topology),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw_instances(render_pass, 0..1);
+    }
+
+    fn draw_instances<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, range: Range<u32>) {
+        match self {
+            Form::SimpleRange { vertex_count } => {
+                render_pass.draw(0..*vertex_count, range);
+            }
+            Form::Vertices(VertexBuffer {
+                vertices,
+                vertex_count,
+                ..
+            }) => {
+                render_pass.set_vertex_buffer(0, vertices.slice(..));
+                render_pass.draw(0..*vertex_count, range);
+            }
+            Form::IndexedVertices(VertexIndexBuffer {
+                vertices,
+                indices,
+                index_count,
+                index_format,
+                ..
+            }) => {
+                render_pass.set_vertex_buffer(0, vertices.slice(..));
+                render_pass.set_index_buffer(indices.slice(..), *index_format);
+                render_pass.draw_indexed(0..*index_count, 0, range);
+            }
+            Form::Instanced { mesh, instances } => {
+                let slot = mesh.buffer_layouts().len() as u32;
+                render_pass.set_vertex_buffer(slot, instances.instances.slice(..));
+                mesh.draw_instances(render_pass, 0..instances.instance_count);
+            }
+        }
+    }
+}
+
+fn buffer_layout(
+    array_stride: u64,
+    step_mode: wgpu::VertexStepMode,
+    attributes: &[wgpu::VertexAttribute],
+) -> wgpu::VertexBufferLayout<'_> {
+    wgpu::VertexBufferLayout {
+        array_stride,
+        step_mode,
+        attributes,
+    }
+}
+
+// Buffers are padded to the copy alignment, so any data can be written later.
+// Empty data still gets a small buffer.
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    data: &[u8],
+    usage: wgpu::BufferUsages,
+) -> (wgpu::Buffer, u64) {
+    let capacity = aligned(data.len() as u64).max(wgpu::COPY_BUFFER_ALIGNMENT);
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: capacity,
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: true,
+    });
+    buffer.slice(..).get_mapped_range_mut()[..data.len()].copy_from_slice(data);
+    buffer.unmap();
+    (buffer, capacity)
+}
+
+// Writes the data to the start of the buffer. Data larger than the buffer
+// replaces it with one of at least twice the capacity, so buffers that grow
+// every frame are only reallocated a few times.
+fn write_buffer(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &mut wgpu::Buffer,
+    capacity: &mut u64,
+    label: &str,
+    data: &[u8],
+    usage: wgpu::BufferUsages,
+) {
+    let size = aligned(data.len() as u64);
+    if size > *capacity {
+        *capacity = size.max(*capacity * 2);
+        *buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: *capacity,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    }
+    if data.len() as u64 == size {
+        queue.write_buffer(buffer, 0, data);
+    } else {
+        let mut padded = data.to_vec();
+        padded.resize(size as usize, 0);
+        queue.write_buffer(buffer, 0, &padded);
+    }
+}
+
+fn aligned(size: u64) -> u64 {
+    size.div_ceil(wgpu::COPY_BUFFER_ALIGNMENT) * wgpu::COPY_BUFFER_ALIGNMENT
+}
+
+pub fn is_strip(topology: wgpu::PrimitiveTopology) -> bool {
+    matches!(
+        topology,
+        wgpu::PrimitiveTopology::LineStrip | wgpu::PrimitiveTopology::TriangleStrip
+    )
+}
+
+fn check_indices<I: VertexIndex>(
+    index_data: &[I],
+    vertex_count: u32,
+    allow_restart: bool,
+) -> Result<(), RendererError> {
+    let out_of_bounds = index_data
+        .iter()
+        .find(|index| index.to_u32() >= vertex_count && !(allow_restart && **index == I::RESTART));
+    match out_of_bounds {
+        Some(index) => Err(RendererError::IndexOutOfBounds {
+            index: index.to_u32(),
+            vertex_count,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn max_index<I: VertexIndex>(index_data: &[I]) -> Option<u32> {
+    index_data
+        .iter()
+        .filter(|index| **index != I::RESTART)
+        .map(|index| index.to_u32())
+        .max()
+}
+
+fn restart_index<I: VertexIndex>(index_data: &[I]) -> Option<u32> {
+    index_data
+        .contains(&I::RESTART)
+        .then(|| I::RESTART.to_u32())
+}
+
+fn check_stride<T>(array_stride: u64) -> Result<(), RendererError> {
+    let actual = std::mem::size_of::<T>() as u64;
+    if actual != array_stride {
+        return Err(RendererError::VertexStride {
+            expected: array_stride,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn out_of_bounds(result: Result<(), RendererError>) -> Option<(u32, u32)> {
+        match result {
+            Ok(()) => None,
+            Err(RendererError::IndexOutOfBounds {
+                index,
+                vertex_count,
+            }) => Some((index, vertex_count)),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn indices_within_the_vertices_pass() {
+        assert_eq!(
+            out_of_bounds(check_indices(&[0u16, 1, 2, 2], 3, false)),
+            None
+        );
+        assert_eq!(out_of_bounds(check_indices::<u32>(&[], 0, false)), None);
+    }
+
+    #[test]
+    fn the_first_index_past_the_vertices_fails() {
+        assert_eq!(
+            out_of_bounds(check_indices(&[0u32, 3, 4], 3, false)),
+            Some((3, 3))
+        );
+        assert_eq!(out_of_bounds(check_indices(&[0u16], 0, true)), Some((0, 0)));
+    }
+
+    #[test]
+    fn restart_is_only_exempt_for_strips() {
+        let strips = [0u16, 1, 2, u16::MAX, 1, 2, 3];
+        assert_eq!(out_of_bounds(check_indices(&strips, 4, true)), None);
+        assert_eq!(
+            out_of_bounds(check_indices(&strips, 4, false)),
+            Some((u16::MAX as u32, 4))
+        );
+        assert_eq!(
+            out_of_bounds(check_indices(&[u32::MAX], 4, false)),
+            Some((u32::MAX, 4))
+        );
+    }
+
+    #[test]
+    fn max_index_skips_restart() {
+        assert_eq!(max_index(&[2u16, u16::MAX, 5, 1]), Some(5));
+        assert_eq!(max_index(&[u32::MAX]), None);
+        assert_eq!(max_index::<u16>(&[]), None);
+        assert_eq!(restart_index(&[2u16, u16::MAX]), Some(u16::MAX as u32));
+        assert_eq!(restart_index(&[2u32, 3]), None);
+    }
+
+    #[test]
+    fn strip_topologies() {
+        assert!(is_strip(wgpu::PrimitiveTopology::TriangleStrip));
+        assert!(is_strip(wgpu::PrimitiveTopology::LineStrip));
+        assert!(!is_strip(wgpu::PrimitiveTopology::TriangleList));
+        assert!(!is_strip(wgpu::PrimitiveTopology::PointList));
+    }
 }
@@ -0,0 +1,238 @@
+use std::path::PathBuf;
+
+use super::{
+    create_offscreen_texture, readback, ComputeHandle, LayerHandle, Output, Renderer, RendererError,
+};
+
+// Records several layers into one command buffer. The surface texture is
+// acquired with the first layer drawn to the surface and presented, together
+// with all recorded layers, by `present`. Dropping a frame discards it.
+pub struct Frame<'a> {
+    renderer: &'a mut Renderer,
+    encoder: wgpu::CommandEncoder,
+    output: Option<Output>,
+    capture: Option<Capture>,
+}
+
+// Surface layers of a frame with a requested screenshot are drawn into this
+// texture instead, as surface textures cannot be copied from. It is drawn
+// onto the surface when presenting.
+struct Capture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    path: PathBuf,
+    drawn: bool,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(renderer: &'a mut Renderer) -> Self {
+        renderer.write_globals();
+        let encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        let capture = renderer.screenshot.take().map(|path| {
+            let config = wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                ..renderer.config.clone()
+            };
+            let texture = create_offscreen_texture(&renderer.device, &config);
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            Capture {
+                texture,
+                view,
+                path,
+                drawn: false,
+            }
+        });
+        Self {
+            renderer,
+            encoder,
+            output: None,
+            capture,
+        }
+    }
+
+    // Layers are drawn in the order they are recorded. A layer without clear
+    // color draws on top of what earlier layers left in its target.
+    pub fn render_layer(&mut self, layer: LayerHandle) -> Result<(), RendererError> {
+        self.renderer.prepare_layer(layer)?;
+        let renderer = &*self.renderer;
+        let layer = renderer.layer(layer)?;
+        let view = match layer.target_texture(renderer)? {
+            Some(texture) => &renderer.texture(texture)?.view,
+            None => {
+                if self.output.is_none() {
+                    self.output = Some(renderer.acquire_output()?);
+                }
+                match &mut self.capture {
+                    Some(capture) => {
+                        capture.drawn = true;
+                        &capture.view
+                    }
+                    None => &self.output.as_ref().unwrap().view,
+                }
+            }
+        };
+        layer.encode(renderer, &mut self.encoder, view)
+    }
+
+    // Computes run in recording order too, so a compute recorded before a
+    // layer finishes before the layer reads its results
+    pub fn dispatch(&mut self, compute: ComputeHandle) -> Result<(), RendererError> {
+        let renderer = &*self.renderer;
+        renderer
+            .compute(compute)?
+            .encode(renderer, &mut self.encoder)
+    }
+
+    pub fn render_layers(&mut self, layers: &[LayerHandle]) -> Result<(), RendererError> {
+        for layer in layers {
+            self.render_layer(*layer)?;
+        }
+        Ok(())
+    }
+
+    pub fn present(mut self) {
+        if let (Some(capture), Some(output)) = (&self.capture, &self.output) {
+            blit(
+                self.renderer,
+                &mut self.encoder,
+                &capture.view,
+                &output.view,
+            );
+        }
+        // submit will accept anything that implements IntoIter
+        self.renderer
+            .queue
+            .submit(std::iter::once(self.encoder.finish()));
+        if let Some(output) = self.output {
+            output.present();
+        }
+        if let Some(capture) = self.capture.filter(|capture| capture.drawn) {
+            let config = &self.renderer.config;
+            let image = readback::read_texture(
+                self.renderer,
+                &capture.texture,
+                config.width,
+                config.height,
+                config.format,
+            )
+            .and_then(|image| {
+                image
+                    .save(&capture.path)
+                    .map_err(|error| RendererError::Image {
+                        path: capture.path.clone(),
+                        error,
+                    })
+            });
+            match image {
+                Ok(()) => log::info!("saved screenshot {}", capture.path.display()),
+                Err(e) => log::error!(
+                    "failed to save screenshot {}: {}",
+                    capture.path.display(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+const BLIT_WGSL: &str = "
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    // One triangle covering the whole target
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+[[group(0), binding(0)]]
+var source: texture_2d<f32>;
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    return textureLoad(source, vec2<i32>(position.xy), 0);
+}
+";
+
+// Draws a texture of the same size and format as the surface onto it.
+// Created per screenshot, as screenshots are rare.
+fn blit(
+    renderer: &Renderer,
+    encoder: &mut wgpu::CommandEncoder,
+    source: &wgpu::TextureView,
+    target: &wgpu::TextureView,
+) {
+    let device = &renderer.device;
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Blit Shader"),
+        source: wgpu::ShaderSource::Wgsl(BLIT_WGSL.into()),
+    });
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Blit Bind Group Layout"),
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Blit Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(source),
+        }],
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Blit Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Blit Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[wgpu::ColorTargetState {
+                format: renderer.config.format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Blit Pass"),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(&pipeline);
+    render_pass.set_bind_group(0, &bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
@@ -0,0 +1,50 @@
+use bytemuck::{Pod, Zeroable};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+// Group the globals are bound at, the last one available by default. The
+// binding index within the group is 0.
+pub const GLOBALS_GROUP: u32 = 3;
+
+// Name shades include the WGSL definition of the globals with
+pub const GLOBALS_INCLUDE: &str = "paintings/globals.wgsl";
+
+pub const GLOBALS_WGSL: &str = include_str!("globals.wgsl");
+
+// Matches the layout of `Globals` in globals.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable)]
+pub struct Globals {
+    pub resolution: [f32; 2],
+    pub mouse: [f32; 2],
+    pub tile_offset: [f32; 2],
+    pub time: f32,
+    pub delta: f32,
+    pub aspect: f32,
+    pub frame: u32,
+    pub mouse_buttons: u32,
+    pub seed: u32,
+}
+
+impl Globals {
+    // Follows the cursor position and the pressed mouse buttons
+    pub fn track_input(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse = [position.x as f32, position.y as f32];
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let bit = match button {
+                    MouseButton::Left => 1,
+                    MouseButton::Right => 2,
+                    MouseButton::Middle => 4,
+                    MouseButton::Other(_) => 0,
+                };
+                match state {
+                    ElementState::Pressed => self.mouse_buttons |= bit,
+                    ElementState::Released => self.mouse_buttons &= !bit,
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,25 @@
+// Globals the renderer updates at the start of every frame. Include with
+// `#include "paintings/globals.wgsl"` in shades of sketches made with
+// `SketchOptions::with_globals`, or of computes binding `Binding::globals`.
+
+struct Globals {
+    // Canvas size in pixels, the whole print when rendering tiles
+    resolution: vec2<f32>;
+    // Cursor position in canvas pixels, y pointing down
+    mouse: vec2<f32>;
+    // Offset of the tile in canvas pixels, add it to the fragment position
+    // when rendering tiles
+    tile_offset: vec2<f32>;
+    // Seconds since the first frame and since the last frame
+    time: f32;
+    delta: f32;
+    // Canvas width divided by height
+    aspect: f32;
+    frame: u32;
+    // Bit 0 for the left, 1 for the right and 2 for the middle button
+    mouse_buttons: u32;
+    seed: u32;
+};
+
+[[group(3), binding(0)]]
+var<uniform> globals: Globals;
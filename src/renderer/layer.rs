@@ -1,15 +1,103 @@
+use winit::dpi::PhysicalSize;
+
 use crate::prelude::*;
 
+// Where a layer draws its sketches. Texture targets can be bound as inputs
+// to sketches of later layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerTarget {
+    Surface,
+    Texture(TextureHandle),
+    // Renders into the side of the feedback that becomes the previous frame
+    // after the renderer swaps them
+    Feedback(FeedbackHandle),
+}
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// The attachment formats of a layer's render pass. Sketches keep one
+// pipeline per pass format of the layers drawing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PassFormat {
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub sample_count: u32,
+}
+
+impl Default for PassFormat {
+    fn default() -> Self {
+        Self {
+            depth_format: None,
+            sample_count: 1,
+        }
+    }
+}
+
+// Depth or multisampled color attachment owned by a layer
+pub struct AttachmentTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: PhysicalSize<u32>,
+    pub sample_count: u32,
+}
+
+impl AttachmentTexture {
+    pub fn new(
+        renderer: &Renderer,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Layer Attachment Texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            size,
+            sample_count,
+        }
+    }
+
+    pub fn matches(&self, size: PhysicalSize<u32>, sample_count: u32) -> bool {
+        self.size == size && self.sample_count == sample_count
+    }
+}
+
 pub struct Layer {
     clear_color: Option<wgpu::Color>,
-    sketch_indices: Vec<usize>,
+    sketches: Vec<SketchHandle>,
+    target: LayerTarget,
+    // Depth is cleared to this value at the start of the layer, if set
+    depth_clear: Option<f32>,
+    // Samples per pixel, more than one renders into `msaa_texture` and
+    // resolves into the target
+    sample_count: u32,
+    // Created by the renderer when the layer is drawn or its target resized
+    depth_texture: Option<AttachmentTexture>,
+    msaa_texture: Option<AttachmentTexture>,
 }
 
 impl Layer {
-    pub fn new(sketch_indices: Vec<usize>) -> Layer {
+    pub fn new(sketches: Vec<SketchHandle>) -> Layer {
         Layer {
             clear_color: None,
-            sketch_indices,
+            sketches,
+            target: LayerTarget::Surface,
+            depth_clear: None,
+            sample_count: 1,
+            depth_texture: None,
+            msaa_texture: None,
         }
     }
 
@@ -23,23 +111,193 @@ impl Layer {
         self
     }
 
-    pub fn render(&self, renderer: &Renderer) -> Result<(), wgpu::SurfaceError> {
-        let output = renderer.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = renderer
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
+    pub fn clear_color(&self) -> Option<wgpu::Color> {
+        self.clear_color
+    }
+
+    pub fn with_target(mut self, target: LayerTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn set_target(&mut self, target: LayerTarget) -> &Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target(&self) -> LayerTarget {
+        self.target
+    }
+
+    // Gives the layer a depth buffer, cleared to the value every time the
+    // layer is drawn. 1.0 is the far plane.
+    pub fn with_depth(mut self, clear_value: f32) -> Self {
+        self.set_depth(Some(clear_value));
+        self
+    }
+
+    pub fn set_depth(&mut self, clear_value: Option<f32>) -> &Self {
+        self.depth_clear = clear_value;
+        if clear_value.is_none() {
+            self.depth_texture = None;
+        }
+        self
+    }
+
+    pub fn depth(&self) -> Option<f32> {
+        self.depth_clear
+    }
+
+    // Multisample anti-aliasing, e.g. 4 for 4x MSAA, which all backends
+    // support. Must be a power of two. Multisampled layers draw into their
+    // own color texture and resolve it over the whole target, so they need a
+    // clear color and cannot draw on top of earlier layers.
+    pub fn with_sample_count(mut self, sample_count: u32) -> Self {
+        self.set_sample_count(sample_count);
+        self
+    }
+
+    pub fn set_sample_count(&mut self, sample_count: u32) -> &Self {
+        self.sample_count = sample_count;
+        if sample_count <= 1 {
+            self.msaa_texture = None;
+        }
+        self
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn depth_texture(&self) -> Option<&AttachmentTexture> {
+        self.depth_texture.as_ref()
+    }
+
+    pub fn set_depth_texture(&mut self, depth_texture: AttachmentTexture) -> &Self {
+        self.depth_texture = Some(depth_texture);
+        self
+    }
+
+    pub fn msaa_texture(&self) -> Option<&AttachmentTexture> {
+        self.msaa_texture.as_ref()
+    }
+
+    pub fn set_msaa_texture(&mut self, msaa_texture: AttachmentTexture) -> &Self {
+        self.msaa_texture = Some(msaa_texture);
+        self
+    }
+
+    pub fn pass_format(&self) -> PassFormat {
+        PassFormat {
+            depth_format: self.depth_clear.map(|_| DEPTH_FORMAT),
+            sample_count: self.sample_count,
+        }
+    }
+
+    pub fn sketches(&self) -> &[SketchHandle] {
+        &self.sketches
+    }
+
+    pub fn set_sketches(&mut self, sketches: Vec<SketchHandle>) -> &Self {
+        self.sketches = sketches;
+        self
+    }
+
+    pub fn remove_sketch(&mut self, sketch: SketchHandle) -> &Self {
+        self.sketches.retain(|s| *s != sketch);
+        self
+    }
+
+    // The texture the layer renders into, or None for the surface
+    pub fn target_texture(
+        &self,
+        renderer: &Renderer,
+    ) -> Result<Option<TextureHandle>, RendererError> {
+        let target = match self.target {
+            LayerTarget::Surface => return Ok(None),
+            LayerTarget::Texture(target) => target,
+            LayerTarget::Feedback(feedback) => renderer
+                .feedback(feedback)?
+                .next(renderer.feedback_swapped()),
+        };
+        if !renderer.texture(target)?.is_render_target() {
+            return Err(RendererError::NotRenderTarget(format!("{:?}", target)));
+        }
+        // A texture cannot be bound while it is being rendered to, neither
+        // directly nor as the previous side of a feedback
+        let binds_target = |resource: &BindingResource| match *resource {
+            BindingResource::Texture(texture) | BindingResource::StorageTexture(texture) => {
+                texture == target
+            }
+            BindingResource::Feedback(feedback) => renderer
+                .feedback(feedback)
+                .is_ok_and(|f| f.previous(renderer.feedback_swapped()) == target),
+            _ => false,
+        };
+        let sampled_by = self.sketches.iter().find(|s| {
+            renderer.sketch(**s).is_ok_and(|s| {
+                s.options
+                    .bindings
+                    .iter()
+                    .any(|binding| binds_target(&binding.resource))
+            })
+        });
+        if let Some(sketch) = sampled_by {
+            return Err(RendererError::TargetSampled {
+                target: format!("{:?}", target),
+                sketch: format!("{:?}", sketch),
             });
+        }
+        Ok(Some(target))
+    }
+
+    // Size of the texture the layer renders into
+    pub fn target_size(&self, renderer: &Renderer) -> Result<PhysicalSize<u32>, RendererError> {
+        match self.target_texture(renderer)? {
+            Some(texture) => Ok(renderer.texture(texture)?.size),
+            None => Ok(renderer.size),
+        }
+    }
+
+    // Records a render pass drawing all sketches into the view. The depth and
+    // multisampled textures and the sketch pipelines for the pass format must
+    // have been prepared with `Renderer::prepare_layer`, otherwise nothing is
+    // recorded and an error returned.
+    pub fn encode(
+        &self,
+        renderer: &Renderer,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) -> Result<(), RendererError> {
+        if (self.sample_count > 1 && self.msaa_texture.is_none())
+            || (self.depth_clear.is_some() && self.depth_texture.is_none())
+        {
+            return Err(RendererError::UnpreparedLayer);
+        }
+        let pass_format = self.pass_format();
+        let mut draws = Vec::with_capacity(self.sketches.len());
+        for sketch in self.sketches.iter() {
+            let sketch = renderer.sketch(*sketch)?;
+            let form = renderer.form(sketch.form)?;
+            let pipeline = sketch
+                .pipeline(pass_format)
+                .ok_or(RendererError::UnpreparedLayer)?;
+            draws.push((
+                pipeline,
+                sketch.bind_groups(renderer.feedback_swapped()),
+                form,
+            ));
+        }
 
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: match &self.msaa_texture {
+                        Some(msaa) => &msaa.view,
+                        None => view,
+                    },
+                    resolve_target: self.msaa_texture.as_ref().map(|_| view),
                     ops: wgpu::Operations {
                         load: match self.clear_color {
                             Some(color) => wgpu::LoadOp::Clear(color),
@@ -48,42 +306,29 @@ impl Layer {
                         store: true,
                     },
                 }],
-                depth_stencil_attachment: None,
-            });
-            for sketch in self.sketch_indices.iter() {
-                let sketch = renderer.sketch(*sketch);
-                let form = renderer.form(sketch.form_idx);
-                render_pass.set_pipeline(&sketch.pipeline); // 2.
-                match form {
-                    Form::SimpleRange { vertex_count } => {
-                        render_pass.draw(0..*vertex_count, 0..1); // 3.
-                    }
-                    Form::Vertices(VertexBuffer {
-                        vertices,
-                        vertex_count,
-                        ..
-                    }) => {
-                        render_pass.set_vertex_buffer(0, vertices.slice(..));
-                        render_pass.draw(0..*vertex_count, 0..1);
-                    }
-                    Form::IndexedVertices(VertexIndexBuffer {
-                        vertices,
-                        vertex_count,
-                        indices,
-                        ..
-                    }) => {
-                        render_pass.set_vertex_buffer(0, vertices.slice(..));
-                        render_pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
-                        render_pass.draw_indexed(0..*vertex_count, 0, 0..1);
+                depth_stencil_attachment: match (self.depth_clear, &self.depth_texture) {
+                    (Some(clear_value), Some(depth)) => {
+                        Some(wgpu::RenderPassDepthStencilAttachment {
+                            view: &depth.view,
+                            depth_ops: Some(wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(clear_value),
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        })
                     }
+                    _ => None,
+                },
+            });
+            for (pipeline, bind_groups, form) in draws {
+                render_pass.set_pipeline(pipeline); // 2.
+                for (group, bind_group) in bind_groups.iter().enumerate() {
+                    render_pass.set_bind_group(group as u32, bind_group, &[]);
                 }
+                form.draw(&mut render_pass); // 3.
             }
         }
 
-        // submit will accept anything that implements IntoIter
-        renderer.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
         Ok(())
     }
 }
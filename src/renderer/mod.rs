@@ -1,172 +1,1462 @@
+use std::path::{Path, PathBuf};
+
 use bytemuck::Pod;
-use winit::{dpi::PhysicalSize, window::Window};
+use winit::{dpi::PhysicalSize, event::WindowEvent, window::Window};
 
+mod arena;
+mod compute;
+mod error;
+mod feedback;
 mod form;
+mod frame;
+mod globals;
 mod layer;
+mod preprocess;
+mod readback;
 mod shade;
 mod sketch;
+mod storage;
+mod texture;
+mod tile;
+mod uniform;
 
 pub mod prelude {
+    pub use super::arena::*;
+    pub use super::compute::*;
+    pub use super::error::*;
+    pub use super::feedback::*;
     pub use super::form::*;
+    pub use super::frame::*;
+    pub use super::globals::*;
     pub use super::layer::*;
+    pub use super::preprocess::*;
     pub use super::shade::*;
     pub use super::sketch::*;
+    pub use super::storage::*;
+    pub use super::texture::*;
+    pub use super::tile::*;
+    pub use super::uniform::*;
     pub use super::*;
 }
 use prelude::*;
 
 pub struct Renderer {
     pub size: PhysicalSize<u32>,
-    pub surface: wgpu::Surface,
+    pub surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
+    // Applied to all shades created afterwards
+    pub preprocessor: Preprocessor,
+
+    // Render target of headless renderers, which have no surface
+    offscreen: Option<wgpu::Texture>,
+    // Where the next presented frame is saved, see `request_screenshot`
+    screenshot: Option<PathBuf>,
+    // Set while rendering part of a larger canvas, see `set_tile`
+    tile: Option<Tile>,
+    // Written into the uniform at the start of every frame
+    globals: Globals,
+    globals_uniform: Uniform,
 
-    shades: Vec<Shade>,
-    forms: Vec<Form>,
-    sketches: Vec<Sketch>,
-    layers: Vec<Layer>,
+    shades: Arena<Shade>,
+    forms: Arena<Form>,
+    sketches: Arena<Sketch>,
+    layers: Arena<Layer>,
+    uniforms: Arena<Uniform>,
+    textures: Arena<Texture>,
+    samplers: Arena<Sampler>,
+    feedbacks: Arena<Feedback>,
+    storage_buffers: Arena<StorageBuffer>,
+    computes: Arena<Compute>,
+    // Flipped at the end of every frame to swap the sides of all feedbacks
+    feedback_swapped: bool,
 }
 
 impl Renderer {
     // Creating some of the wgpu types requires async code
-    pub async fn new(window: &Window) -> Renderer {
+    pub async fn new(window: &Window) -> Result<Renderer, RendererError> {
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let backends = wgpu::Backends::all();
+        let instance = wgpu::Instance::new(backends);
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+        let options = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        };
+        let adapter = instance.request_adapter(&options).await;
+        let adapter = adapter.ok_or(RendererError::AdapterNotFound {
+            backends,
+            power_preference: options.power_preference,
+            force_fallback_adapter: options.force_fallback_adapter,
+        })?;
+
+        let (device, queue) = request_device(&adapter).await?;
 
         let size = window.inner_size();
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_preferred_format(&adapter).unwrap(),
+            format: surface
+                .get_preferred_format(&adapter)
+                .ok_or(RendererError::IncompatibleSurface)?,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
         surface.configure(&device, &config);
 
+        Ok(Self::from_parts(device, queue, config, Some(surface), None))
+    }
+
+    // Creates a renderer without a window. Layers render into an offscreen
+    // texture of the given size, which can be read back with `read_image`.
+    pub async fn new_headless(width: u32, height: u32) -> Result<Renderer, RendererError> {
+        if width == 0 || height == 0 {
+            return Err(RendererError::EmptySize { width, height });
+        }
+        let backends = wgpu::Backends::all();
+        let power_preference = wgpu::PowerPreference::default();
+        let instance = wgpu::Instance::new(backends);
+        let mut adapter = None;
+        // Prefer real hardware, but fall back to a software adapter (e.g. on CI)
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference,
+                    compatible_surface: None,
+                    force_fallback_adapter,
+                })
+                .await;
+            if adapter.is_some() {
+                break;
+            }
+        }
+        let adapter = adapter.ok_or(RendererError::AdapterNotFound {
+            backends,
+            power_preference,
+            force_fallback_adapter: true,
+        })?;
+
+        let (device, queue) = request_device(&adapter).await?;
+
+        // Never used to configure a surface, but keeps format and size in the
+        // same place as for windowed renderers
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let offscreen = create_offscreen_texture(&device, &config);
+
+        Ok(Self::from_parts(
+            device,
+            queue,
+            config,
+            None,
+            Some(offscreen),
+        ))
+    }
+
+    // Shared by windowed and headless renderers, the size is taken from the config
+    fn from_parts(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        surface: Option<wgpu::Surface>,
+        offscreen: Option<wgpu::Texture>,
+    ) -> Self {
+        let globals_uniform = Uniform::with_device(&device, &Globals::default());
         Self {
+            size: PhysicalSize::new(config.width, config.height),
             surface,
             device,
             queue,
             config,
-            size,
-            shades: vec![],
-            forms: vec![],
-            sketches: vec![],
-            layers: vec![],
+            preprocessor: Preprocessor::new(),
+            offscreen,
+            screenshot: None,
+            tile: None,
+            globals: Globals::default(),
+            globals_uniform,
+            shades: Arena::new(),
+            forms: Arena::new(),
+            sketches: Arena::new(),
+            layers: Arena::new(),
+            uniforms: Arena::new(),
+            textures: Arena::new(),
+            samplers: Arena::new(),
+            feedbacks: Arena::new(),
+            storage_buffers: Arena::new(),
+            computes: Arena::new(),
+            feedback_swapped: false,
         }
     }
 
     pub fn resize(&mut self, window: &Window) {
-        let new_size = window.inner_size();
+        self.set_size(window.inner_size());
+    }
+
+    pub fn set_size(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
+            self.tile = None;
             self.config.width = self.size.width;
             self.config.height = self.size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
+            if self.offscreen.is_some() {
+                self.offscreen = Some(create_offscreen_texture(&self.device, &self.config));
+            }
+            if let Err(e) = self.resize_render_targets() {
+                log::error!("failed to resize render targets: {}", e);
+            }
+            let layers: Vec<LayerHandle> = self.layers.iter().map(|(handle, _)| handle).collect();
+            for layer in layers {
+                if let Err(e) = self.prepare_layer(layer) {
+                    log::error!("failed to prepare layer {:?}: {}", layer, e);
+                }
+            }
+        }
+    }
+
+    // Creates or resizes the depth and multisampled textures of the layer to
+    // match its target, and the pipelines its sketches need for the layer's
+    // pass format
+    pub fn prepare_layer(&mut self, handle: LayerHandle) -> Result<(), RendererError> {
+        let layer = self.layer(handle)?;
+        let sample_count = layer.sample_count();
+        if !sample_count.is_power_of_two() {
+            return Err(RendererError::InvalidSampleCount(sample_count));
+        }
+        if sample_count > 1 && layer.clear_color().is_none() {
+            return Err(RendererError::MultisampledLoad(format!("{:?}", handle)));
+        }
+        let size = layer.target_size(self)?;
+        if layer.depth().is_some()
+            && !layer
+                .depth_texture()
+                .is_some_and(|depth| depth.matches(size, sample_count))
+        {
+            let depth_texture = AttachmentTexture::new(self, size, DEPTH_FORMAT, sample_count);
+            self.layer_mut(handle)?.set_depth_texture(depth_texture);
         }
+        let layer = self.layer(handle)?;
+        if sample_count > 1
+            && !layer
+                .msaa_texture()
+                .is_some_and(|msaa| msaa.matches(size, sample_count))
+        {
+            let msaa_texture = AttachmentTexture::new(self, size, self.config.format, sample_count);
+            self.layer_mut(handle)?.set_msaa_texture(msaa_texture);
+        }
+
+        let layer = self.layer(handle)?;
+        let pass_format = layer.pass_format();
+        let mut pipelines = vec![];
+        for sketch_handle in layer.sketches() {
+            let sketch = self.sketch(*sketch_handle)?;
+            if sketch.pipeline(pass_format).is_none() {
+                pipelines.push((*sketch_handle, sketch.create_pipeline(self, pass_format)?));
+            }
+        }
+        for (sketch, pipeline) in pipelines {
+            self.sketch_mut(sketch)?
+                .pipelines
+                .push((pass_format, pipeline));
+        }
+        Ok(())
+    }
+
+    // Recreates the render targets sized relative to the surface and
+    // rebuilds the bind groups of the sketches sampling them
+    fn resize_render_targets(&mut self) -> Result<(), RendererError> {
+        let resized: Vec<(TextureHandle, Texture)> = self
+            .textures
+            .iter()
+            .filter_map(|(handle, texture)| match texture.target_size {
+                Some(target_size) if target_size.is_relative() => {
+                    Some((handle, Texture::render_target(self, target_size)))
+                }
+                _ => None,
+            })
+            .collect();
+        if resized.is_empty() {
+            return Ok(());
+        }
+        let mut resources: Vec<BindingResource> = resized
+            .iter()
+            .map(|(handle, _)| BindingResource::Texture(*handle))
+            .collect();
+        resources.extend(
+            self.feedbacks
+                .iter()
+                .filter(|(_, feedback)| {
+                    feedback
+                        .targets
+                        .iter()
+                        .any(|target| resized.iter().any(|(handle, _)| handle == target))
+                })
+                .map(|(handle, _)| BindingResource::Feedback(handle)),
+        );
+        for (handle, texture) in resized {
+            *self.texture_mut(handle)? = texture;
+        }
+        self.rebuild_sketches(|sketch| resources.iter().any(|r| sketch.uses(*r)))?;
+        self.rebuild_computes(|compute| resources.iter().any(|r| compute.uses(*r)))
+    }
+
+    // Resizes the renderer to the tile, which stays set until the next
+    // resize. Render targets sized relative to the surface follow the tile
+    // size, not the canvas size.
+    pub fn set_tile(&mut self, tile: Tile) {
+        self.set_size(tile.size);
+        self.tile = Some(tile);
     }
 
-    pub fn make_form_simple_range(&mut self, vertex_count: u32) -> usize {
+    // The part of the canvas being rendered, the whole surface unless
+    // rendering tiles
+    pub fn tile(&self) -> Tile {
+        self.tile.unwrap_or_else(|| Tile::full(self.size))
+    }
+
+    // Size of the whole painting, for aspect ratios and pixel coordinates
+    pub fn canvas_size(&self) -> PhysicalSize<u32> {
+        self.tile().canvas_size
+    }
+
+    pub fn globals(&self) -> &Globals {
+        &self.globals
+    }
+
+    pub fn globals_uniform(&self) -> &Uniform {
+        &self.globals_uniform
+    }
+
+    // Called once per frame before rendering, usually with the frame clock
+    pub fn set_time(&mut self, time: f64, delta: f64, frame: u64) {
+        self.globals.time = time as f32;
+        self.globals.delta = delta as f32;
+        self.globals.frame = frame as u32;
+    }
+
+    // For shades drawing random patterns, fixed so renderings repeat
+    pub fn set_seed(&mut self, seed: u32) {
+        self.globals.seed = seed;
+    }
+
+    // Follows mouse events of the window for the globals
+    pub fn track_input(&mut self, event: &WindowEvent) {
+        self.globals.track_input(event);
+    }
+
+    // Writes the globals with the current canvas and tile
+    fn write_globals(&mut self) {
+        let tile = self.tile();
+        let canvas_size = tile.canvas_size;
+        self.globals.resolution = [canvas_size.width as f32, canvas_size.height as f32];
+        self.globals.aspect = canvas_size.width as f32 / canvas_size.height as f32;
+        self.globals.tile_offset = [tile.offset.x as f32, tile.offset.y as f32];
+        self.queue.write_buffer(
+            &self.globals_uniform.buffer,
+            0,
+            bytemuck::bytes_of(&self.globals),
+        );
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    // Reads the offscreen target of a headless renderer back into an image.
+    // Only valid after the rendering commands have been submitted.
+    pub fn read_image(&self) -> Result<image::RgbaImage, RendererError> {
+        let texture = self.offscreen.as_ref().ok_or(RendererError::NotHeadless)?;
+        readback::read_texture(
+            self,
+            texture,
+            self.config.width,
+            self.config.height,
+            self.config.format,
+        )
+    }
+
+    // Saves the surface layers of the next presented frame as a PNG at the
+    // surface size, without window decorations. Frames without surface
+    // layers are not saved.
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.screenshot = Some(path.into());
+    }
+
+    // Acquires the texture the current frame is drawn into: the next surface
+    // texture for windowed renderers, the offscreen texture otherwise.
+    pub fn acquire_output(&self) -> Result<Output, wgpu::SurfaceError> {
+        match &self.surface {
+            Some(surface) => {
+                let texture = surface.get_current_texture()?;
+                let view = texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Output {
+                    view,
+                    surface_texture: Some(texture),
+                })
+            }
+            None => {
+                let view = self
+                    .offscreen
+                    .as_ref()
+                    .expect("headless renderer without offscreen texture")
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Output {
+                    view,
+                    surface_texture: None,
+                })
+            }
+        }
+    }
+
+    pub fn make_form_simple_range(
+        &mut self,
+        vertex_count: u32,
+    ) -> Result<FormHandle, RendererError> {
         let form = Form::SimpleRange { vertex_count };
-        self.forms.push(form);
-        self.forms.len() - 1
+        Ok(self.forms.insert(form))
     }
 
     pub fn make_form_vertices<T: Pod>(
         &mut self,
         vertex_data: &[T],
         attributes: &[wgpu::VertexAttribute],
-    ) -> usize {
+    ) -> Result<FormHandle, RendererError> {
         let form = Form::Vertices(VertexBuffer::new(self, vertex_data, attributes));
-        self.forms.push(form);
-        self.forms.len() - 1
+        Ok(self.forms.insert(form))
     }
 
-    pub fn make_form_indexed_vertices<T: Pod>(
+    // Indices are u16 or u32 and must be below the number of vertices
+    pub fn make_form_indexed_vertices<T: Pod, I: VertexIndex>(
         &mut self,
         vertex_data: &[T],
         attributes: &[wgpu::VertexAttribute],
-        index_data: &[i32],
-    ) -> usize {
-        let form = Form::IndexedVertices(VertexIndexBuffer::new(
-            self,
-            vertex_data,
-            attributes,
-            index_data,
-        ));
-        self.forms.push(form);
-        self.forms.len() - 1
+        index_data: &[I],
+    ) -> Result<FormHandle, RendererError> {
+        let form = Form::indexed_vertices(self, vertex_data, attributes, index_data)?;
+        Ok(self.forms.insert(form))
     }
 
-    pub fn make_shade(&mut self, shader_source: &'static str) -> usize {
-        let shade = Shade::new(self, shader_source);
-        self.shades.push(shade);
-        self.shades.len() - 1
+    // Draws the mesh, e.g. a form made with `Form::vertices`, once per
+    // element of the instance data. Instance attributes need shader locations
+    // distinct from the mesh attributes.
+    pub fn make_form_instanced<T: Pod>(
+        &mut self,
+        mesh: Form,
+        instance_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+    ) -> Result<FormHandle, RendererError> {
+        let form = self.instanced_form(mesh, instance_data, attributes)?;
+        Ok(self.forms.insert(form))
     }
 
-    pub fn make_sketch(&mut self, shade_idx: usize, form_idx: usize) -> usize {
-        let sketch = Sketch::new(self, shade_idx, form_idx);
-        self.sketches.push(sketch);
-        self.sketches.len() - 1
+    fn instanced_form<T: Pod>(
+        &self,
+        mesh: Form,
+        instance_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+    ) -> Result<Form, RendererError> {
+        if mesh.is_instanced() {
+            return Err(RendererError::NestedInstancing);
+        }
+        Ok(Form::Instanced {
+            mesh: Box::new(mesh),
+            instances: InstanceBuffer::new(self, instance_data, attributes),
+        })
+    }
+
+    // Writes new vertices into the form, growing its buffer if needed. The
+    // vertex type must match the one the form was created with. For
+    // instanced forms the mesh vertices are updated.
+    pub fn update_form_vertices<T: Pod>(
+        &mut self,
+        handle: FormHandle,
+        vertex_data: &[T],
+    ) -> Result<(), RendererError> {
+        let form = self
+            .forms
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))?;
+        let mesh = match form {
+            Form::Instanced { mesh, .. } => mesh.as_mut(),
+            form => form,
+        };
+        match mesh {
+            Form::Vertices(buf) => buf.update(&self.device, &self.queue, vertex_data),
+            Form::IndexedVertices(buf) => {
+                buf.update_vertices(&self.device, &self.queue, vertex_data)
+            }
+            _ => Err(RendererError::MissingFormBuffer("vertex buffer")),
+        }
+    }
+
+    // Writes new indices into an indexed form and draws that many indices
+    pub fn update_form_indices<I: VertexIndex>(
+        &mut self,
+        handle: FormHandle,
+        index_data: &[I],
+    ) -> Result<(), RendererError> {
+        let allow_restart = self
+            .sketches
+            .iter()
+            .filter(|(_, sketch)| sketch.form == handle)
+            .all(|(_, sketch)| is_strip(sketch.options.topology));
+        let form = self
+            .forms
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))?;
+        let mesh = match form {
+            Form::Instanced { mesh, .. } => mesh.as_mut(),
+            form => form,
+        };
+        match mesh {
+            Form::IndexedVertices(buf) => {
+                buf.update_indices(&self.device, &self.queue, index_data, allow_restart)
+            }
+            _ => Err(RendererError::MissingFormBuffer("index buffer")),
+        }
+    }
+
+    // Writes new instances into an instanced form and draws that many instances
+    pub fn update_form_instances<T: Pod>(
+        &mut self,
+        handle: FormHandle,
+        instance_data: &[T],
+    ) -> Result<(), RendererError> {
+        let form = self
+            .forms
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))?;
+        match form {
+            Form::Instanced { instances, .. } => {
+                instances.update(&self.device, &self.queue, instance_data)
+            }
+            _ => Err(RendererError::MissingFormBuffer("instance buffer")),
+        }
+    }
+
+    pub fn make_shade(&mut self, shader_source: &str) -> Result<ShadeHandle, RendererError> {
+        let shade = Shade::new(self, shader_source, ShadeStage::Render)?;
+        Ok(self.shades.insert(shade))
+    }
+
+    // Shades loaded from a file are reloaded by `reload_modified_shades`
+    pub fn make_shade_from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<ShadeHandle, RendererError> {
+        let shade = Shade::from_file(self, path.as_ref(), ShadeStage::Render)?;
+        Ok(self.shades.insert(shade))
+    }
+
+    // Compute shades have a `cs_main` compute entry point and are used by
+    // computes instead of sketches
+    pub fn make_compute_shade(
+        &mut self,
+        shader_source: &str,
+    ) -> Result<ShadeHandle, RendererError> {
+        let shade = Shade::new(self, shader_source, ShadeStage::Compute)?;
+        Ok(self.shades.insert(shade))
+    }
+
+    pub fn make_compute_shade_from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<ShadeHandle, RendererError> {
+        let shade = Shade::from_file(self, path.as_ref(), ShadeStage::Compute)?;
+        Ok(self.shades.insert(shade))
+    }
+
+    pub fn make_sketch(
+        &mut self,
+        shade: ShadeHandle,
+        form: FormHandle,
+        options: SketchOptions,
+    ) -> Result<SketchHandle, RendererError> {
+        let sketch = Sketch::new(self, shade, form, options, &[PassFormat::default()])?;
+        Ok(self.sketches.insert(sketch))
+    }
+
+    pub fn make_compute(
+        &mut self,
+        shade: ShadeHandle,
+        bindings: Vec<Binding>,
+        dispatch: Dispatch,
+    ) -> Result<ComputeHandle, RendererError> {
+        let compute = Compute::new(self, shade, bindings, dispatch)?;
+        Ok(self.computes.insert(compute))
+    }
+
+    pub fn replace_compute(
+        &mut self,
+        handle: ComputeHandle,
+        shade: ShadeHandle,
+        bindings: Vec<Binding>,
+    ) -> Result<(), RendererError> {
+        let dispatch = self.compute(handle)?.dispatch;
+        let compute = Compute::new(self, shade, bindings, dispatch)?;
+        *self.compute_mut(handle)? = compute;
+        Ok(())
+    }
+
+    // Submits the compute on its own, outside of any frame
+    pub fn dispatch(&mut self, handle: ComputeHandle) -> Result<(), RendererError> {
+        self.write_globals();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+        self.compute(handle)?.encode(self, &mut encoder)?;
+        self.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+
+    pub fn make_storage_buffer<T: Pod>(
+        &mut self,
+        data: &[T],
+    ) -> Result<StorageBufferHandle, RendererError> {
+        let buffer = StorageBuffer::new(self, data);
+        Ok(self.storage_buffers.insert(buffer))
+    }
+
+    pub fn make_storage_buffer_zeroed(
+        &mut self,
+        size: u64,
+    ) -> Result<StorageBufferHandle, RendererError> {
+        let buffer = StorageBuffer::zeroed(self, size);
+        Ok(self.storage_buffers.insert(buffer))
+    }
+
+    // Replaces the whole content of the buffer, e.g. to reset a simulation
+    pub fn update_storage_buffer<T: Pod>(
+        &self,
+        handle: StorageBufferHandle,
+        data: &[T],
+    ) -> Result<(), RendererError> {
+        let buffer = self.storage_buffer(handle)?;
+        let data: &[u8] = bytemuck::cast_slice(data);
+        if data.len() as u64 != buffer.size {
+            return Err(RendererError::StorageSize {
+                expected: buffer.size,
+                actual: data.len() as u64,
+            });
+        }
+        self.queue.write_buffer(&buffer.buffer, 0, data);
+        Ok(())
+    }
+
+    pub fn make_uniform<T: Pod>(&mut self, data: &T) -> Result<UniformHandle, RendererError> {
+        let uniform = Uniform::new(self, data);
+        Ok(self.uniforms.insert(uniform))
+    }
+
+    // Writes new data into the uniform buffer, visible to the next submitted frame
+    pub fn update_uniform<T: Pod>(
+        &self,
+        handle: UniformHandle,
+        data: &T,
+    ) -> Result<(), RendererError> {
+        let uniform = self.uniform(handle)?;
+        let data = bytemuck::bytes_of(data);
+        if data.len() as u64 != uniform.size {
+            return Err(RendererError::UniformSize {
+                expected: uniform.size,
+                actual: data.len() as u64,
+            });
+        }
+        self.queue.write_buffer(&uniform.buffer, 0, data);
+        Ok(())
+    }
+
+    // Binds the uniform to the sketch, replacing any resource previously
+    // bound at the same group and binding, and rebuilds the sketch
+    pub fn attach_uniform(
+        &mut self,
+        sketch: SketchHandle,
+        uniform: UniformHandle,
+        group: u32,
+        binding: u32,
+    ) -> Result<(), RendererError> {
+        self.attach(sketch, &[Binding::uniform(group, binding, uniform)])
+    }
+
+    pub fn make_texture_from_image(
+        &mut self,
+        image: &image::DynamicImage,
+    ) -> Result<TextureHandle, RendererError> {
+        let texture = Texture::from_image(self, image);
+        Ok(self.textures.insert(texture))
+    }
+
+    // Loads any format supported by the image crate, e.g. PNG or JPEG
+    pub fn make_texture_from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<TextureHandle, RendererError> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|error| RendererError::Image {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        self.make_texture_from_image(&image)
+    }
+
+    pub fn make_texture_empty(
+        &mut self,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+    ) -> Result<TextureHandle, RendererError> {
+        let texture = Texture::empty(self, size, format);
+        Ok(self.textures.insert(texture))
+    }
+
+    // Replaces the whole content of the texture with tightly packed texels
+    pub fn update_texture<T: Pod>(
+        &self,
+        handle: TextureHandle,
+        data: &[T],
+    ) -> Result<(), RendererError> {
+        let texture = self.texture(handle)?;
+        let data: &[u8] = bytemuck::cast_slice(data);
+        if data.len() as u64 != texture.byte_size() {
+            return Err(RendererError::TextureSize {
+                expected: texture.byte_size(),
+                actual: data.len() as u64,
+            });
+        }
+        texture.write(self, data);
+        Ok(())
+    }
+
+    // A texture computes can write into with `Binding::storage_texture`, and
+    // which sketches can sample
+    pub fn make_storage_texture(
+        &mut self,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+    ) -> Result<TextureHandle, RendererError> {
+        let texture = Texture::storage(self, size, format);
+        Ok(self.textures.insert(texture))
+    }
+
+    // A texture layers can render into with `LayerTarget::Texture`, and which
+    // sketches of later layers can sample
+    pub fn make_render_target(&mut self, size: TargetSize) -> Result<TextureHandle, RendererError> {
+        let texture = Texture::render_target(self, size);
+        Ok(self.textures.insert(texture))
+    }
+
+    // Both sides start out transparent black, use `seed_feedback_color` or
+    // `seed_feedback_image` for a different start
+    pub fn make_feedback(&mut self, size: TargetSize) -> Result<FeedbackHandle, RendererError> {
+        let targets = [
+            self.make_render_target(size)?,
+            self.make_render_target(size)?,
+        ];
+        Ok(self.feedbacks.insert(Feedback::new(targets)))
+    }
+
+    // Seeds write both sides, so the first frame reads the seed no matter
+    // which side is current
+    pub fn seed_feedback_color(
+        &self,
+        handle: FeedbackHandle,
+        color: wgpu::Color,
+    ) -> Result<(), RendererError> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Feedback Seed Encoder"),
+            });
+        for target in self.feedback(handle)?.targets {
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Feedback Seed Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.texture(target)?.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(color),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+
+    // The image is scaled to the feedback size
+    pub fn seed_feedback_image(
+        &self,
+        handle: FeedbackHandle,
+        image: &image::DynamicImage,
+    ) -> Result<(), RendererError> {
+        for target in self.feedback(handle)?.targets {
+            self.texture(target)?.write_image(self, image)?;
+        }
+        Ok(())
     }
 
-    pub fn make_layer(&mut self, sketches: Vec<usize>) -> usize {
+    // Marks the end of a frame by swapping the sides of all feedbacks, also
+    // those not rendered into this frame. A feedback skipped for a frame
+    // then shows its older side, so paintings that update a feedback only
+    // on some frames should copy it forward on the others.
+    pub fn end_frame(&mut self) {
+        self.feedback_swapped = !self.feedback_swapped;
+    }
+
+    pub fn feedback_swapped(&self) -> bool {
+        self.feedback_swapped
+    }
+
+    pub fn make_sampler(
+        &mut self,
+        options: SamplerOptions,
+    ) -> Result<SamplerHandle, RendererError> {
+        let sampler = Sampler::new(self, options);
+        Ok(self.samplers.insert(sampler))
+    }
+
+    // Binds the texture at `binding` and the sampler at `binding + 1` of the
+    // group, matching the usual texture and sampler pair in WGSL
+    pub fn attach_texture(
+        &mut self,
+        sketch: SketchHandle,
+        texture: TextureHandle,
+        sampler: SamplerHandle,
+        group: u32,
+        binding: u32,
+    ) -> Result<(), RendererError> {
+        self.attach(
+            sketch,
+            &[
+                Binding::texture(group, binding, texture),
+                Binding::sampler(group, binding + 1, sampler),
+            ],
+        )
+    }
+
+    fn attach(&mut self, handle: SketchHandle, new: &[Binding]) -> Result<(), RendererError> {
+        let sketch = self.sketch(handle)?;
+        let mut options = sketch.options.clone();
+        for binding in new {
+            options.set_binding(*binding);
+        }
+        let pass_formats = sketch.pass_formats();
+        let sketch = Sketch::new(self, sketch.shade, sketch.form, options, &pass_formats)?;
+        *self.sketch_mut(handle)? = sketch;
+        Ok(())
+    }
+
+    pub fn make_layer(
+        &mut self,
+        sketches: Vec<SketchHandle>,
+    ) -> Result<LayerHandle, RendererError> {
+        for sketch in sketches.iter() {
+            self.sketch(*sketch)?;
+        }
         let layer = Layer::new(sketches);
-        self.layers.push(layer);
-        self.layers.len() - 1
+        Ok(self.layers.insert(layer))
+    }
+
+    // Rebuilds the sketches drawing the form. On any error the previous form
+    // and sketches are kept.
+    pub fn replace_form(&mut self, handle: FormHandle, form: Form) -> Result<(), RendererError> {
+        let previous = std::mem::replace(self.form_mut(handle)?, form);
+        let sketches = match self.build_sketches(|sketch| sketch.form == handle) {
+            Ok(sketches) => sketches,
+            Err(e) => {
+                *self.form_mut(handle)? = previous;
+                return Err(e);
+            }
+        };
+        for (handle, sketch) in sketches {
+            *self.sketch_mut(handle)? = sketch;
+        }
+        Ok(())
+    }
+
+    pub fn replace_form_simple_range(
+        &mut self,
+        handle: FormHandle,
+        vertex_count: u32,
+    ) -> Result<(), RendererError> {
+        self.replace_form(handle, Form::SimpleRange { vertex_count })
+    }
+
+    pub fn replace_form_vertices<T: Pod>(
+        &mut self,
+        handle: FormHandle,
+        vertex_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+    ) -> Result<(), RendererError> {
+        let form = Form::Vertices(VertexBuffer::new(self, vertex_data, attributes));
+        self.replace_form(handle, form)
+    }
+
+    pub fn replace_form_indexed_vertices<T: Pod, I: VertexIndex>(
+        &mut self,
+        handle: FormHandle,
+        vertex_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+        index_data: &[I],
+    ) -> Result<(), RendererError> {
+        let form = Form::indexed_vertices(self, vertex_data, attributes, index_data)?;
+        self.replace_form(handle, form)
+    }
+
+    pub fn replace_form_instanced<T: Pod>(
+        &mut self,
+        handle: FormHandle,
+        mesh: Form,
+        instance_data: &[T],
+        attributes: &[wgpu::VertexAttribute],
+    ) -> Result<(), RendererError> {
+        let form = self.instanced_form(mesh, instance_data, attributes)?;
+        self.replace_form(handle, form)
+    }
+
+    // Rebuilds the sketches and computes using the shade. On any error the
+    // previous shade and pipelines are kept.
+    pub fn replace_shade(
+        &mut self,
+        handle: ShadeHandle,
+        shader_source: &str,
+    ) -> Result<(), RendererError> {
+        let stage = self.shade(handle)?.stage;
+        let shade = Shade::new(self, shader_source, stage)?;
+        let previous = std::mem::replace(self.shade_mut(handle)?, shade);
+        let built = self
+            .build_sketches(|sketch| sketch.shade == handle)
+            .and_then(|sketches| {
+                let computes = self.build_computes(|compute| compute.shade == handle)?;
+                Ok((sketches, computes))
+            });
+        let (sketches, computes) = match built {
+            Ok(built) => built,
+            Err(e) => {
+                *self.shade_mut(handle)? = previous;
+                return Err(e);
+            }
+        };
+        for (handle, sketch) in sketches {
+            *self.sketch_mut(handle)? = sketch;
+        }
+        for (handle, compute) in computes {
+            *self.compute_mut(handle)? = compute;
+        }
+        Ok(())
+    }
+
+    // Recompiles a file based shade and rebuilds the sketches and computes
+    // using it. On any error the previous shade and pipelines are kept.
+    pub fn reload_shade(&mut self, handle: ShadeHandle) -> Result<(), RendererError> {
+        let shade = self.shade(handle)?;
+        let stage = shade.stage;
+        let path = match &shade.path {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let rebuilt = Shade::from_file(self, &path, stage).and_then(|shade| {
+            let previous = std::mem::replace(self.shade_mut(handle)?, shade);
+            let built = self
+                .build_sketches(|sketch| sketch.shade == handle)
+                .and_then(|sketches| {
+                    let computes = self.build_computes(|compute| compute.shade == handle)?;
+                    Ok((sketches, computes))
+                });
+            Ok((previous, built))
+        });
+        let gpu_error = pollster::block_on(self.device.pop_error_scope());
+
+        let (previous, built) = rebuilt?;
+        let (sketches, computes) = match (built, gpu_error) {
+            (Ok(built), None) => built,
+            (Err(e), _) => {
+                *self.shade_mut(handle)? = previous;
+                return Err(e);
+            }
+            (Ok(_), Some(e)) => {
+                *self.shade_mut(handle)? = previous;
+                return Err(RendererError::Gpu(e.to_string()));
+            }
+        };
+        for (handle, sketch) in sketches {
+            *self.sketch_mut(handle)? = sketch;
+        }
+        for (handle, compute) in computes {
+            *self.compute_mut(handle)? = compute;
+        }
+        Ok(())
+    }
+
+    // Reloads all shades whose file changed since the last check. Errors are
+    // logged, so a broken shader never stops the painting.
+    pub fn reload_modified_shades(&mut self) {
+        let modified: Vec<ShadeHandle> = self
+            .shades
+            .iter_mut()
+            .filter_map(|(handle, shade)| shade.check_modified().then_some(handle))
+            .collect();
+        for handle in modified {
+            match self.reload_shade(handle) {
+                Ok(()) => log::info!("reloaded shade {:?}", handle),
+                Err(e) => log::error!("failed to reload shade {:?}: {}", handle, e),
+            }
+        }
+    }
+
+    pub fn replace_sketch(
+        &mut self,
+        handle: SketchHandle,
+        shade: ShadeHandle,
+        form: FormHandle,
+    ) -> Result<(), RendererError> {
+        let sketch = self.sketch(handle)?;
+        let options = sketch.options.clone();
+        let pass_formats = sketch.pass_formats();
+        let sketch = Sketch::new(self, shade, form, options, &pass_formats)?;
+        *self.sketch_mut(handle)? = sketch;
+        Ok(())
+    }
+
+    pub fn replace_sketch_options(
+        &mut self,
+        handle: SketchHandle,
+        options: SketchOptions,
+    ) -> Result<(), RendererError> {
+        let sketch = self.sketch(handle)?;
+        let pass_formats = sketch.pass_formats();
+        let sketch = Sketch::new(self, sketch.shade, sketch.form, options, &pass_formats)?;
+        *self.sketch_mut(handle)? = sketch;
+        Ok(())
+    }
+
+    pub fn replace_layer(
+        &mut self,
+        handle: LayerHandle,
+        sketches: Vec<SketchHandle>,
+    ) -> Result<(), RendererError> {
+        for sketch in sketches.iter() {
+            self.sketch(*sketch)?;
+        }
+        self.layer_mut(handle)?.set_sketches(sketches);
+        Ok(())
+    }
+
+    // Forms and shades can only be removed once no sketch uses them anymore.
+    // Dropping the returned value frees the GPU resources.
+    pub fn remove_form(&mut self, handle: FormHandle) -> Result<Form, RendererError> {
+        if let Some((sketch, _)) = self.sketches.iter().find(|(_, s)| s.form == handle) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: format!("{:?}", sketch),
+            });
+        }
+        self.forms
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    pub fn remove_shade(&mut self, handle: ShadeHandle) -> Result<Shade, RendererError> {
+        if let Some((sketch, _)) = self.sketches.iter().find(|(_, s)| s.shade == handle) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: format!("{:?}", sketch),
+            });
+        }
+        if let Some((compute, _)) = self.computes.iter().find(|(_, c)| c.shade == handle) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: format!("{:?}", compute),
+            });
+        }
+        self.shades
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    // Removing a sketch also removes it from all layers drawing it
+    pub fn remove_sketch(&mut self, handle: SketchHandle) -> Result<Sketch, RendererError> {
+        let sketch = self
+            .sketches
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))?;
+        for (_, layer) in self.layers.iter_mut() {
+            layer.remove_sketch(handle);
+        }
+        Ok(sketch)
     }
 
-    pub fn form(&self, idx: usize) -> &Form {
-        self.forms.get(idx).expect("form index invalid")
+    pub fn remove_uniform(&mut self, handle: UniformHandle) -> Result<Uniform, RendererError> {
+        if let Some(user) = self.binding_user(&[BindingResource::Uniform(handle)]) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: user,
+            });
+        }
+        self.uniforms
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    pub fn remove_texture(&mut self, handle: TextureHandle) -> Result<Texture, RendererError> {
+        let resources = [
+            BindingResource::Texture(handle),
+            BindingResource::StorageTexture(handle),
+        ];
+        if let Some(user) = self.binding_user(&resources) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: user,
+            });
+        }
+        let target = LayerTarget::Texture(handle);
+        if let Some((layer, _)) = self.layers.iter().find(|(_, l)| l.target() == target) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: format!("{:?}", layer),
+            });
+        }
+        if let Some((feedback, _)) = self
+            .feedbacks
+            .iter()
+            .find(|(_, f)| f.targets.contains(&handle))
+        {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: format!("{:?}", feedback),
+            });
+        }
+        self.textures
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    pub fn remove_sampler(&mut self, handle: SamplerHandle) -> Result<Sampler, RendererError> {
+        if let Some(user) = self.binding_user(&[BindingResource::Sampler(handle)]) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: user,
+            });
+        }
+        self.samplers
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    // Also removes both textures of the feedback
+    pub fn remove_feedback(&mut self, handle: FeedbackHandle) -> Result<Feedback, RendererError> {
+        if let Some(user) = self.binding_user(&[BindingResource::Feedback(handle)]) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: user,
+            });
+        }
+        let target = LayerTarget::Feedback(handle);
+        if let Some((layer, _)) = self.layers.iter().find(|(_, l)| l.target() == target) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: format!("{:?}", layer),
+            });
+        }
+        let feedback = self
+            .feedbacks
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))?;
+        for target in feedback.targets {
+            self.textures.remove(target);
+        }
+        Ok(feedback)
+    }
+
+    pub fn remove_storage_buffer(
+        &mut self,
+        handle: StorageBufferHandle,
+    ) -> Result<StorageBuffer, RendererError> {
+        if let Some(user) = self.binding_user(&[BindingResource::StorageBuffer(handle)]) {
+            return Err(RendererError::ResourceInUse {
+                resource: format!("{:?}", handle),
+                used_by: user,
+            });
+        }
+        self.storage_buffers
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    pub fn remove_compute(&mut self, handle: ComputeHandle) -> Result<Compute, RendererError> {
+        self.computes
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    // The first sketch or compute binding any of the resources, or a compute
+    // dispatched over one of them
+    fn binding_user(&self, resources: &[BindingResource]) -> Option<String> {
+        if let Some((sketch, _)) = self
+            .sketches
+            .iter()
+            .find(|(_, s)| resources.iter().any(|r| s.uses(*r)))
+        {
+            return Some(format!("{:?}", sketch));
+        }
+        self.computes
+            .iter()
+            .find(|(_, c)| resources.iter().any(|r| c.uses(*r)))
+            .map(|(compute, _)| format!("{:?}", compute))
+    }
+
+    pub fn remove_layer(&mut self, handle: LayerHandle) -> Result<Layer, RendererError> {
+        self.layers
+            .remove(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    fn rebuild_sketches(
+        &mut self,
+        predicate: impl Fn(&Sketch) -> bool,
+    ) -> Result<(), RendererError> {
+        for (handle, sketch) in self.build_sketches(predicate)? {
+            *self.sketch_mut(handle)? = sketch;
+        }
+        Ok(())
+    }
+
+    fn build_sketches(
+        &self,
+        predicate: impl Fn(&Sketch) -> bool,
+    ) -> Result<Vec<(SketchHandle, Sketch)>, RendererError> {
+        self.sketches
+            .iter()
+            .filter(|(_, sketch)| predicate(sketch))
+            .map(|(handle, sketch)| {
+                let options = sketch.options.clone();
+                let pass_formats = sketch.pass_formats();
+                Ok((
+                    handle,
+                    Sketch::new(self, sketch.shade, sketch.form, options, &pass_formats)?,
+                ))
+            })
+            .collect()
+    }
+
+    fn rebuild_computes(
+        &mut self,
+        predicate: impl Fn(&Compute) -> bool,
+    ) -> Result<(), RendererError> {
+        for (handle, compute) in self.build_computes(predicate)? {
+            *self.compute_mut(handle)? = compute;
+        }
+        Ok(())
+    }
+
+    fn build_computes(
+        &self,
+        predicate: impl Fn(&Compute) -> bool,
+    ) -> Result<Vec<(ComputeHandle, Compute)>, RendererError> {
+        self.computes
+            .iter()
+            .filter(|(_, compute)| predicate(compute))
+            .map(|(handle, compute)| {
+                let bindings = compute.bindings.clone();
+                Ok((
+                    handle,
+                    Compute::new(self, compute.shade, bindings, compute.dispatch)?,
+                ))
+            })
+            .collect()
+    }
+
+    pub fn form(&self, handle: FormHandle) -> Result<&Form, RendererError> {
+        self.forms
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+    pub fn shade(&self, handle: ShadeHandle) -> Result<&Shade, RendererError> {
+        self.shades
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
-    pub fn shade(&self, idx: usize) -> &Shade {
-        self.shades.get(idx).expect("shade index invalid")
+    pub fn sketch(&self, handle: SketchHandle) -> Result<&Sketch, RendererError> {
+        self.sketches
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
-    pub fn sketch(&self, idx: usize) -> &Sketch {
-        self.sketches.get(idx).expect("sketch index invalid")
+    pub fn layer(&self, handle: LayerHandle) -> Result<&Layer, RendererError> {
+        self.layers
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
-    pub fn layer(&self, idx: usize) -> &Layer {
-        self.layers.get(idx).expect("layer index invalid")
+
+    pub fn uniform(&self, handle: UniformHandle) -> Result<&Uniform, RendererError> {
+        self.uniforms
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
 
-    pub fn form_mut(&mut self, idx: usize) -> &mut Form {
-        self.forms.get_mut(idx).expect("form index invalid")
+    pub fn texture(&self, handle: TextureHandle) -> Result<&Texture, RendererError> {
+        self.textures
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
-    pub fn shade_mut(&mut self, idx: usize) -> &mut Shade {
-        self.shades.get_mut(idx).expect("shade index invalid")
+
+    pub fn sampler(&self, handle: SamplerHandle) -> Result<&Sampler, RendererError> {
+        self.samplers
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
-    pub fn sketch_mut(&mut self, idx: usize) -> &mut Sketch {
-        self.sketches.get_mut(idx).expect("sketch index invalid")
+
+    pub fn feedback(&self, handle: FeedbackHandle) -> Result<&Feedback, RendererError> {
+        self.feedbacks
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
-    pub fn layer_mut(&mut self, idx: usize) -> &mut Layer {
-        self.layers.get_mut(idx).expect("layer index invalid")
+
+    pub fn storage_buffer(
+        &self,
+        handle: StorageBufferHandle,
+    ) -> Result<&StorageBuffer, RendererError> {
+        self.storage_buffers
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
     }
 
-    pub fn render_layer(&self, idx: usize) -> Result<(), wgpu::SurfaceError> {
-        self.layer(idx).render(self)
+    pub fn compute(&self, handle: ComputeHandle) -> Result<&Compute, RendererError> {
+        self.computes
+            .get(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    pub fn form_mut(&mut self, handle: FormHandle) -> Result<&mut Form, RendererError> {
+        self.forms
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+    pub fn texture_mut(&mut self, handle: TextureHandle) -> Result<&mut Texture, RendererError> {
+        self.textures
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+    pub fn shade_mut(&mut self, handle: ShadeHandle) -> Result<&mut Shade, RendererError> {
+        self.shades
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+    pub fn sketch_mut(&mut self, handle: SketchHandle) -> Result<&mut Sketch, RendererError> {
+        self.sketches
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+    pub fn compute_mut(&mut self, handle: ComputeHandle) -> Result<&mut Compute, RendererError> {
+        self.computes
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+    pub fn layer_mut(&mut self, handle: LayerHandle) -> Result<&mut Layer, RendererError> {
+        self.layers
+            .get_mut(handle)
+            .ok_or_else(|| RendererError::invalid_handle(handle))
+    }
+
+    // Starts recording a frame, see `Frame`
+    pub fn begin_frame(&mut self) -> Frame<'_> {
+        Frame::new(self)
+    }
+
+    // Renders and presents a frame with only this layer
+    pub fn render_layer(&mut self, layer: LayerHandle) -> Result<(), RendererError> {
+        self.render_layers(&[layer])
+    }
+
+    // Renders and presents a frame with all layers, drawn in order
+    pub fn render_layers(&mut self, layers: &[LayerHandle]) -> Result<(), RendererError> {
+        let mut frame = self.begin_frame();
+        frame.render_layers(layers)?;
+        frame.present();
+        Ok(())
+    }
+}
+
+pub struct Output {
+    pub view: wgpu::TextureView,
+    surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+impl Output {
+    // Presents the surface texture, if any. Offscreen output needs no presenting.
+    pub fn present(self) {
+        if let Some(texture) = self.surface_texture {
+            texture.present();
+        }
+    }
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), RendererError> {
+    let device = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                // Optional, checked when creating sketches that need them
+                features: adapter.features()
+                    & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::POLYGON_MODE_POINT),
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None, // Trace path
+        )
+        .await?;
+    Ok(device)
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_renderers_reject_empty_sizes() {
+        for (width, height) in [(0, 0), (0, 16), (16, 0)] {
+            match pollster::block_on(Renderer::new_headless(width, height)) {
+                Err(RendererError::EmptySize { .. }) => {}
+                Err(e) => panic!("unexpected error: {}", e),
+                Ok(_) => panic!("created a {}x{} renderer", width, height),
+            }
+        }
     }
 }
@@ -0,0 +1,576 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    shade::{snippet, snippet_line},
+    ShadeError, ShadeErrorKind, GLOBALS_INCLUDE, GLOBALS_WGSL,
+};
+
+// Includes provided by the renderer itself, found before any file
+const BUILTIN_INCLUDES: &[(&str, &str)] = &[(GLOBALS_INCLUDE, GLOBALS_WGSL)];
+
+// Resolves `#include "file.wgsl"`, substitutes `#define` constants and
+// evaluates `#ifdef`/`#ifndef`/`#else`/`#endif` blocks before WGSL parsing.
+// Each file is included at most once per shade, so shared helpers can be
+// included from several places without duplicate definitions.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    include_dirs: Vec<PathBuf>,
+    defines: BTreeMap<String, String>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Includes are looked up relative to the including file first, then in
+    // the include directories in the order they were added
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.add_include_dir(dir);
+        self
+    }
+
+    pub fn with_define(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.define(name, value);
+        self
+    }
+
+    pub fn add_include_dir(&mut self, dir: impl Into<PathBuf>) -> &Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    pub fn define(&mut self, name: impl Into<String>, value: impl ToString) -> &Self {
+        self.defines.insert(name.into(), value.to_string());
+        self
+    }
+
+    pub fn undefine(&mut self, name: &str) -> &Self {
+        self.defines.remove(name);
+        self
+    }
+
+    pub fn process_file(&self, path: &Path) -> Result<Preprocessed, ShadeError> {
+        let source = std::fs::read_to_string(path).map_err(|e| ShadeError {
+            kind: ShadeErrorKind::Preprocess,
+            file: Some(path.to_path_buf()),
+            message: format!("cannot read file: {}", e),
+            line: 0,
+            column: 0,
+            snippet: String::new(),
+        })?;
+        let mut state = State::new(self);
+        state.included.insert(canonical(path));
+        state.files.push(path.to_path_buf());
+        state.process(&source, Some(path))?;
+        Ok(state.finish())
+    }
+
+    pub fn process_str(&self, source: &str) -> Result<Preprocessed, ShadeError> {
+        let mut state = State::new(self);
+        state.process(source, None)?;
+        Ok(state.finish())
+    }
+
+    fn resolve(&self, name: &str, from: Option<&Path>) -> Option<PathBuf> {
+        let relative = from.and_then(Path::parent).map(|dir| dir.join(name));
+        relative
+            .into_iter()
+            .chain(self.include_dirs.iter().map(|dir| dir.join(name)))
+            .find(|path| path.is_file())
+    }
+}
+
+// Where a line of preprocessed output came from. `file` is None for shades
+// created from a string.
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub text: String,
+    // Defines replaced in the line, in order
+    pub substitutions: Vec<Substitution>,
+}
+
+// Byte ranges of a define name in the original line and of its value in
+// the preprocessed line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Substitution {
+    pub original: Range<usize>,
+    pub output: Range<usize>,
+}
+
+impl SourceLine {
+    // Maps a 1-based column of the preprocessed line to the original line.
+    // Columns within a substituted value point at the start of the name.
+    pub fn original_column(&self, column: usize) -> usize {
+        let offset = column.saturating_sub(1);
+        let mut shift = 0;
+        for substitution in &self.substitutions {
+            if offset < substitution.output.start {
+                break;
+            }
+            if offset < substitution.output.end {
+                return substitution.original.start + 1;
+            }
+            shift = substitution.original.end as isize - substitution.output.end as isize;
+        }
+        (offset as isize + shift).max(0) as usize + 1
+    }
+}
+
+pub struct Preprocessed {
+    pub source: String,
+    // One entry per line of `source`
+    pub lines: Vec<SourceLine>,
+    // Every file read, including the main file, for change detection
+    pub files: Vec<PathBuf>,
+}
+
+impl Preprocessed {
+    // Points an error in the preprocessed source back at the original file,
+    // line and column
+    pub fn map_error(&self, error: ShadeError) -> ShadeError {
+        match self.lines.get(error.line.wrapping_sub(1)) {
+            Some(origin) => {
+                let column = match error.column {
+                    0 => 0,
+                    column => origin.original_column(column),
+                };
+                ShadeError {
+                    file: origin.file.clone(),
+                    line: origin.line,
+                    column,
+                    snippet: snippet_line(&origin.text, origin.line, column),
+                    ..error
+                }
+            }
+            None => error,
+        }
+    }
+}
+
+struct State<'a> {
+    preprocessor: &'a Preprocessor,
+    defines: BTreeMap<String, String>,
+    included: HashSet<PathBuf>,
+    files: Vec<PathBuf>,
+    output: String,
+    lines: Vec<SourceLine>,
+}
+
+struct Condition {
+    active: bool,
+    parent_active: bool,
+    has_else: bool,
+    line: usize,
+}
+
+impl<'a> State<'a> {
+    fn new(preprocessor: &'a Preprocessor) -> Self {
+        Self {
+            preprocessor,
+            defines: preprocessor.defines.clone(),
+            included: HashSet::new(),
+            files: vec![],
+            output: String::new(),
+            lines: vec![],
+        }
+    }
+
+    fn finish(self) -> Preprocessed {
+        Preprocessed {
+            source: self.output,
+            lines: self.lines,
+            files: self.files,
+        }
+    }
+
+    fn process(&mut self, source: &str, file: Option<&Path>) -> Result<(), ShadeError> {
+        let mut conditions: Vec<Condition> = vec![];
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let error = |message: String| ShadeError {
+                kind: ShadeErrorKind::Preprocess,
+                file: file.map(Path::to_path_buf),
+                message,
+                line,
+                column: 1,
+                snippet: snippet(source, line, 1),
+            };
+            let active = conditions.last().is_none_or(|c| c.active);
+
+            let trimmed = text.trim_start();
+            if !trimmed.starts_with('#') {
+                if active {
+                    let (output, substitutions) = substitute(text, &self.defines);
+                    self.emit(&output, substitutions, file, line, text);
+                }
+                continue;
+            }
+
+            let mut parts = trimmed[1..].splitn(2, char::is_whitespace);
+            let directive = parts.next().unwrap_or("");
+            let argument = parts.next().unwrap_or("").trim();
+
+            match directive {
+                "ifdef" | "ifndef" => {
+                    let name = identifier(argument)
+                        .ok_or_else(|| error(format!("#{} expects a name", directive)))?;
+                    let defined = self.defines.contains_key(name);
+                    conditions.push(Condition {
+                        active: active && (defined == (directive == "ifdef")),
+                        parent_active: active,
+                        has_else: false,
+                        line,
+                    });
+                }
+                "else" => {
+                    let condition = conditions
+                        .last_mut()
+                        .ok_or_else(|| error("#else without #ifdef".into()))?;
+                    if condition.has_else {
+                        return Err(error("duplicate #else".into()));
+                    }
+                    condition.has_else = true;
+                    condition.active = condition.parent_active && !condition.active;
+                }
+                "endif" => {
+                    conditions
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef".into()))?;
+                }
+                _ if !active => {}
+                "define" => {
+                    let mut parts = argument.splitn(2, char::is_whitespace);
+                    let name = parts
+                        .next()
+                        .and_then(identifier)
+                        .ok_or_else(|| error("#define expects a name".into()))?;
+                    let value = parts.next().unwrap_or("").trim();
+                    let (value, _) = substitute(value, &self.defines);
+                    self.defines.insert(name.to_string(), value);
+                }
+                "undef" => {
+                    let name = identifier(argument)
+                        .ok_or_else(|| error("#undef expects a name".into()))?;
+                    self.defines.remove(name);
+                }
+                "include" => {
+                    let name = argument
+                        .strip_prefix('"')
+                        .and_then(|a| a.strip_suffix('"'))
+                        .ok_or_else(|| error("#include expects a quoted path".into()))?;
+                    if let Some((_, builtin)) = BUILTIN_INCLUDES.iter().find(|(n, _)| *n == name) {
+                        let path = PathBuf::from(name);
+                        if self.included.insert(path.clone()) {
+                            self.process(builtin, Some(&path))?;
+                        }
+                        continue;
+                    }
+                    let path = self
+                        .preprocessor
+                        .resolve(name, file)
+                        .ok_or_else(|| error(format!("cannot find include \"{}\"", name)))?;
+                    if self.included.insert(canonical(&path)) {
+                        let included = std::fs::read_to_string(&path).map_err(|e| {
+                            error(format!("cannot read include \"{}\": {}", name, e))
+                        })?;
+                        self.files.push(path.clone());
+                        self.process(&included, Some(&path))?;
+                    }
+                }
+                _ => return Err(error(format!("unknown directive #{}", directive))),
+            }
+        }
+
+        match conditions.last() {
+            Some(condition) => Err(ShadeError {
+                kind: ShadeErrorKind::Preprocess,
+                file: file.map(Path::to_path_buf),
+                message: "#ifdef without #endif".into(),
+                line: condition.line,
+                column: 1,
+                snippet: snippet(source, condition.line, 1),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn emit(
+        &mut self,
+        output: &str,
+        substitutions: Vec<Substitution>,
+        file: Option<&Path>,
+        line: usize,
+        text: &str,
+    ) {
+        self.output.push_str(output);
+        self.output.push('\n');
+        self.lines.push(SourceLine {
+            file: file.map(Path::to_path_buf),
+            line,
+            text: text.to_string(),
+            substitutions,
+        });
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn identifier(text: &str) -> Option<&str> {
+    let mut chars = text.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    valid.then_some(text)
+}
+
+// Replaces whole identifiers with their defined values, leaving line
+// comments untouched
+fn substitute(text: &str, defines: &BTreeMap<String, String>) -> (String, Vec<Substitution>) {
+    if defines.is_empty() {
+        return (text.to_string(), vec![]);
+    }
+    let (code, comment) = match text.find("//") {
+        Some(i) => text.split_at(i),
+        None => (text, ""),
+    };
+
+    let mut result = String::with_capacity(text.len());
+    let mut substitutions = vec![];
+    let mut word_start = 0;
+    for (i, c) in code.char_indices() {
+        if c.is_alphanumeric() || c == '_' {
+            continue;
+        }
+        flush_word(
+            &mut result,
+            &mut substitutions,
+            code,
+            word_start..i,
+            defines,
+        );
+        result.push(c);
+        word_start = i + c.len_utf8();
+    }
+    flush_word(
+        &mut result,
+        &mut substitutions,
+        code,
+        word_start..code.len(),
+        defines,
+    );
+    result.push_str(comment);
+    (result, substitutions)
+}
+
+fn flush_word(
+    result: &mut String,
+    substitutions: &mut Vec<Substitution>,
+    code: &str,
+    word: Range<usize>,
+    defines: &BTreeMap<String, String>,
+) {
+    match defines.get(&code[word.clone()]) {
+        Some(value) => {
+            let start = result.len();
+            result.push_str(value);
+            substitutions.push(Substitution {
+                original: word,
+                output: start..result.len(),
+            });
+        }
+        None => result.push_str(&code[word]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(source: &str) -> String {
+        Preprocessor::new()
+            .with_define("A", 1)
+            .process_str(source)
+            .unwrap()
+            .source
+    }
+
+    // A fresh directory in the system temp dir, removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "paintings-preprocess-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn write(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn nested_conditions() {
+        let source = "\
+#ifdef A
+a
+#ifdef B
+ab
+#else
+a_not_b
+#ifndef C
+a_not_b_not_c
+#endif
+#endif
+#else
+not_a
+#ifdef B
+not_a_b
+#else
+not_a_not_b
+#endif
+#endif";
+        assert_eq!(process(source), "a\na_not_b\na_not_b_not_c\n");
+    }
+
+    #[test]
+    fn defines_inside_inactive_blocks_are_ignored() {
+        let source = "#ifdef B\n#define X 1\n#else\n#define X 2\n#endif\nX";
+        assert_eq!(process(source), "2\n");
+    }
+
+    #[test]
+    fn unbalanced_conditions_are_errors() {
+        let preprocessor = Preprocessor::new();
+        for (source, line) in [("#ifdef A\nx", 1), ("x\n#endif", 2), ("#else", 1)] {
+            let error = preprocessor.process_str(source).err().unwrap();
+            assert_eq!(error.kind, ShadeErrorKind::Preprocess);
+            assert_eq!(error.line, line, "{}", source);
+        }
+        assert!(preprocessor
+            .process_str("#ifdef A\n#else\n#else\n#endif")
+            .is_err());
+    }
+
+    #[test]
+    fn defines_substitute_whole_identifiers_outside_comments() {
+        let source = "\
+#define SIZE 64
+#define HALF SIZE / 2
+let x = SIZE + A_SIZE + HALF; // SIZE stays
+#undef SIZE
+SIZE";
+        assert_eq!(
+            process(source),
+            "let x = 64 + A_SIZE + 64 / 2; // SIZE stays\nSIZE\n"
+        );
+    }
+
+    #[test]
+    fn files_are_included_once() {
+        let dir = TempDir::new("include");
+        dir.write("common.wgsl", "#include \"math.wgsl\"\ncommon");
+        dir.write("math.wgsl", "math");
+        let main = dir.write(
+            "main.wgsl",
+            "#include \"common.wgsl\"\n#include \"math.wgsl\"\nmain",
+        );
+
+        let preprocessed = Preprocessor::new().process_file(&main).unwrap();
+        assert_eq!(preprocessed.source, "math\ncommon\nmain\n");
+        assert_eq!(preprocessed.files.len(), 3);
+        let origins: Vec<_> = preprocessed
+            .lines
+            .iter()
+            .map(|l| (l.file.as_ref().unwrap().file_name().unwrap(), l.line))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                ("math.wgsl".as_ref(), 1),
+                ("common.wgsl".as_ref(), 2),
+                ("main.wgsl".as_ref(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn includes_fall_back_to_include_dirs() {
+        let dir = TempDir::new("include-dirs");
+        std::fs::create_dir(dir.0.join("lib")).unwrap();
+        dir.write("lib/noise.wgsl", "noise");
+        let preprocessed = Preprocessor::new()
+            .with_include_dir(dir.0.join("lib"))
+            .process_str("#include \"noise.wgsl\"")
+            .unwrap();
+        assert_eq!(preprocessed.source, "noise\n");
+        assert!(Preprocessor::new()
+            .process_str("#include \"noise.wgsl\"")
+            .is_err());
+    }
+
+    fn error_at(line: usize, column: usize) -> ShadeError {
+        ShadeError {
+            kind: ShadeErrorKind::Parse,
+            file: None,
+            message: String::new(),
+            line,
+            column,
+            snippet: String::new(),
+        }
+    }
+
+    #[test]
+    fn errors_map_to_the_original_line() {
+        let preprocessed = Preprocessor::new()
+            .process_str("#define X 1\n#ifdef Y\nskipped\n#endif\nfirst\nsecond")
+            .unwrap();
+        let error = preprocessed.map_error(error_at(2, 3));
+        assert_eq!((error.line, error.column), (6, 3));
+        assert_eq!(error.snippet, "6 | second\n  |   ^");
+    }
+
+    #[test]
+    fn errors_map_to_the_original_column() {
+        let preprocessed = Preprocessor::new()
+            .with_define("LONG_NAME", 1)
+            .with_define("N", 100)
+            .process_str("let x = LONG_NAME + N + y;")
+            .unwrap();
+        assert_eq!(preprocessed.source, "let x = 1 + 100 + y;\n");
+        let column = |column| preprocessed.map_error(error_at(1, column)).column;
+        // Before, inside and after the substituted values
+        assert_eq!(column(5), 5);
+        assert_eq!(column(9), 9);
+        assert_eq!(column(13), 21);
+        assert_eq!(column(15), 21);
+        assert_eq!(column(19), 25);
+        assert_eq!(column(0), 0);
+        assert_eq!(
+            preprocessed.map_error(error_at(1, 19)).snippet,
+            "1 | let x = LONG_NAME + N + y;\n  |                         ^"
+        );
+    }
+}
@@ -1,18 +1,369 @@
-use super::Renderer;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use super::{Preprocessed, Renderer, RendererError};
 
 pub struct Shade {
     pub shader: wgpu::ShaderModule,
+    // Set for shades loaded from a file, which are reloaded when it or one
+    // of its includes changes
+    pub path: Option<PathBuf>,
+    pub stage: ShadeStage,
+    // Resource bindings (group, binding) used by the entry points
+    pub bindings: Vec<(u32, u32)>,
+    // Storage buffer bindings declared `read_write`
+    pub writable_bindings: Vec<(u32, u32)>,
+    // Workgroup size of the compute entry point, 1 for render shades
+    pub workgroup_size: [u32; 3],
+    dependencies: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+// Entry points `Sketch::new` builds its render pipelines with
+pub const RENDER_ENTRY_POINTS: &[(&str, naga::ShaderStage)] = &[
+    ("vs_main", naga::ShaderStage::Vertex),
+    ("fs_main", naga::ShaderStage::Fragment),
+];
+
+// Entry point `Compute::new` builds its compute pipeline with
+pub const COMPUTE_ENTRY_POINTS: &[(&str, naga::ShaderStage)] =
+    &[("cs_main", naga::ShaderStage::Compute)];
+
+// Render shades are drawn by sketches, compute shades dispatched by computes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadeStage {
+    Render,
+    Compute,
+}
+
+impl ShadeStage {
+    pub fn entry_points(&self) -> &'static [(&'static str, naga::ShaderStage)] {
+        match self {
+            ShadeStage::Render => RENDER_ENTRY_POINTS,
+            ShadeStage::Compute => COMPUTE_ENTRY_POINTS,
+        }
+    }
 }
 
 impl Shade {
-    pub fn new(renderer: &Renderer, shader_source: &'static str) -> Self {
-        Self {
+    // The source goes through the renderer's preprocessor, includes are
+    // resolved from its include directories
+    pub fn new(
+        renderer: &Renderer,
+        shader_source: &str,
+        stage: ShadeStage,
+    ) -> Result<Self, RendererError> {
+        let preprocessed = renderer.preprocessor.process_str(shader_source)?;
+        Self::compile(renderer, &preprocessed, stage)
+    }
+
+    pub fn from_file(
+        renderer: &Renderer,
+        path: &Path,
+        stage: ShadeStage,
+    ) -> Result<Self, RendererError> {
+        let preprocessed = renderer.preprocessor.process_file(path)?;
+        // Taken before compiling, so changes during compilation are not missed
+        let dependencies = preprocessed
+            .files
+            .iter()
+            .map(|file| (file.clone(), modified_time(file)))
+            .collect();
+        let shade = Self::compile(renderer, &preprocessed, stage).map_err(|e| match e {
+            RendererError::Shade(e) if e.file.is_none() => RendererError::Shade(e.in_file(path)),
+            e => e,
+        })?;
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            dependencies,
+            ..shade
+        })
+    }
+
+    fn compile(
+        renderer: &Renderer,
+        preprocessed: &Preprocessed,
+        stage: ShadeStage,
+    ) -> Result<Self, RendererError> {
+        let (module, info) = validate_wgsl(&preprocessed.source, stage.entry_points())
+            .map_err(|e| preprocessed.map_error(e))?;
+        let workgroup_size = module
+            .entry_points
+            .iter()
+            .find(|ep| ep.stage == naga::ShaderStage::Compute)
+            .map_or([1, 1, 1], |ep| ep.workgroup_size);
+        Ok(Self {
             shader: renderer
                 .device
                 .create_shader_module(&wgpu::ShaderModuleDescriptor {
                     label: Some("Shader"),
-                    source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+                    source: wgpu::ShaderSource::Wgsl(preprocessed.source.as_str().into()),
                 }),
+            path: None,
+            stage,
+            bindings: used_bindings(&module, &info),
+            writable_bindings: writable_bindings(&module),
+            workgroup_size,
+            dependencies: vec![],
+        })
+    }
+
+    // Whether the file of the shade or one of its includes changed since it
+    // was last checked
+    pub fn check_modified(&mut self) -> bool {
+        let mut changed = false;
+        for (path, modified) in self.dependencies.iter_mut() {
+            let current = modified_time(path);
+            if current != *modified {
+                *modified = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+// Collects the resource bindings any entry point accesses
+pub fn used_bindings(module: &naga::Module, info: &naga::valid::ModuleInfo) -> Vec<(u32, u32)> {
+    let mut bindings: Vec<(u32, u32)> = module
+        .global_variables
+        .iter()
+        .filter(|(handle, _)| {
+            (0..module.entry_points.len()).any(|i| !info.get_entry_point(i)[*handle].is_empty())
+        })
+        .filter_map(|(_, var)| var.binding.as_ref())
+        .map(|binding| (binding.group, binding.binding))
+        .collect();
+    bindings.sort_unstable();
+    bindings
+}
+
+fn writable_bindings(module: &naga::Module) -> Vec<(u32, u32)> {
+    module
+        .global_variables
+        .iter()
+        .filter(|(_, var)| match var.class {
+            naga::StorageClass::Storage { access } => access.contains(naga::StorageAccess::STORE),
+            _ => false,
+        })
+        .filter_map(|(_, var)| var.binding.as_ref())
+        .map(|binding| (binding.group, binding.binding))
+        .collect()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Parses and validates WGSL with naga, so errors can be reported with their
+// location instead of surfacing as a wgpu validation panic.
+pub fn validate_wgsl(
+    source: &str,
+    entry_points: &[(&str, naga::ShaderStage)],
+) -> Result<(naga::Module, naga::valid::ModuleInfo), ShadeError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| {
+        let (line, column) = e.location(source);
+        ShadeError::new(ShadeErrorKind::Parse, e.to_string(), source, line, column)
+    })?;
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    );
+    let info = validator.validate(&module).map_err(|e| {
+        let offset = e
+            .spans()
+            .find_map(|(span, _)| span.to_range())
+            .map_or(0, |range| range.start);
+        let (line, column) = line_column(source, offset);
+        ShadeError::new(
+            ShadeErrorKind::Validation,
+            error_chain(&e),
+            source,
+            line,
+            column,
+        )
+    })?;
+
+    for (name, stage) in entry_points {
+        let found = module
+            .entry_points
+            .iter()
+            .any(|ep| ep.name == *name && ep.stage == *stage);
+        if !found {
+            return Err(ShadeError {
+                kind: ShadeErrorKind::MissingEntryPoint,
+                file: None,
+                message: format!("missing {:?} entry point `{}`", stage, name),
+                line: 0,
+                column: 0,
+                snippet: String::new(),
+            });
+        }
+    }
+
+    Ok((module, info))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadeErrorKind {
+    Preprocess,
+    Parse,
+    Validation,
+    MissingEntryPoint,
+}
+
+// Line and column are 1-based, and 0 if the error has no source location
+#[derive(Debug, Clone)]
+pub struct ShadeError {
+    pub kind: ShadeErrorKind,
+    pub file: Option<PathBuf>,
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+}
+
+impl ShadeError {
+    fn new(
+        kind: ShadeErrorKind,
+        message: String,
+        source: &str,
+        line: usize,
+        column: usize,
+    ) -> Self {
+        Self {
+            kind,
+            file: None,
+            message,
+            line,
+            column,
+            snippet: snippet(source, line, column),
+        }
+    }
+
+    pub fn in_file(self, path: &Path) -> Self {
+        Self {
+            file: Some(path.to_path_buf()),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for ShadeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ShadeErrorKind::Preprocess => "preprocessor error",
+            ShadeErrorKind::Parse => "parse error",
+            ShadeErrorKind::Validation => "validation error",
+            ShadeErrorKind::MissingEntryPoint => "entry point error",
+        };
+        write!(f, "shader {}", kind)?;
+        match (&self.file, self.line) {
+            (Some(path), 0) => write!(f, " in {}", path.display())?,
+            (Some(path), line) => write!(f, " at {}:{}:{}", path.display(), line, self.column)?,
+            (None, 0) => {}
+            (None, line) => write!(f, " at {}:{}", line, self.column)?,
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.snippet.is_empty() {
+            write!(f, "\n{}", self.snippet)?;
         }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShadeError {}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+// The offending line with its number and a caret under the column
+pub(crate) fn snippet(source: &str, line: usize, column: usize) -> String {
+    match source.lines().nth(line.saturating_sub(1)) {
+        Some(text) => snippet_line(text, line, column),
+        None => String::new(),
+    }
+}
+
+pub(crate) fn snippet_line(text: &str, line: usize, column: usize) -> String {
+    let number = line.to_string();
+    format!(
+        "{} | {}\n{} | {}^",
+        number,
+        text,
+        " ".repeat(number.len()),
+        " ".repeat(column.saturating_sub(1))
+    )
+}
+
+// Validation errors describe the actual problem in their source chain
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "\
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main() -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0);
+}
+";
+
+    #[test]
+    fn valid_shaders_pass() {
+        assert!(validate_wgsl(SHADER, RENDER_ENTRY_POINTS).is_ok());
+    }
+
+    #[test]
+    fn syntax_errors_point_at_their_location() {
+        let source = SHADER.replace("vec4<f32>(1.0);", "vec4<f32>(1.0) +;");
+        let error = validate_wgsl(&source, RENDER_ENTRY_POINTS).err().unwrap();
+        assert_eq!(error.kind, ShadeErrorKind::Parse);
+        // At the semicolon after the dangling operator
+        assert_eq!((error.line, error.column), (8, 28));
+        assert_eq!(
+            error.snippet,
+            format!("8 |     return vec4<f32>(1.0) +;\n  | {}^", " ".repeat(27))
+        );
+    }
+
+    #[test]
+    fn validation_errors_point_at_their_location() {
+        let source = SHADER.replace("vec4<f32>(1.0);", "1.0;");
+        let error = validate_wgsl(&source, RENDER_ENTRY_POINTS).err().unwrap();
+        assert_eq!(error.kind, ShadeErrorKind::Validation);
+        // At the returned value of the wrong type
+        assert_eq!((error.line, error.column), (8, 11));
+        assert!(error.snippet.starts_with("8 |     return 1.0;"));
+    }
+
+    #[test]
+    fn missing_entry_points_are_reported() {
+        let error = validate_wgsl(SHADER, COMPUTE_ENTRY_POINTS).err().unwrap();
+        assert_eq!(error.kind, ShadeErrorKind::MissingEntryPoint);
+        assert!(error.message.contains("cs_main"), "{}", error.message);
+        assert_eq!((error.line, error.column), (0, 0));
     }
 }
@@ -1,33 +1,346 @@
 use crate::prelude::*;
 
+// A resource bound to a sketch at the given bind group and binding index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub group: u32,
+    pub binding: u32,
+    pub resource: BindingResource,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingResource {
+    Uniform(UniformHandle),
+    Texture(TextureHandle),
+    Sampler(SamplerHandle),
+    // Binds the side of the feedback holding the previous frame
+    Feedback(FeedbackHandle),
+    // Read-only or `read_write`, as declared by the shade
+    StorageBuffer(StorageBufferHandle),
+    // Write-only, for textures made with `make_storage_texture`
+    StorageTexture(TextureHandle),
+    // The renderer's globals uniform
+    Globals,
+}
+
+impl Binding {
+    pub fn uniform(group: u32, binding: u32, uniform: UniformHandle) -> Self {
+        Self {
+            group,
+            binding,
+            resource: BindingResource::Uniform(uniform),
+        }
+    }
+
+    pub fn texture(group: u32, binding: u32, texture: TextureHandle) -> Self {
+        Self {
+            group,
+            binding,
+            resource: BindingResource::Texture(texture),
+        }
+    }
+
+    pub fn sampler(group: u32, binding: u32, sampler: SamplerHandle) -> Self {
+        Self {
+            group,
+            binding,
+            resource: BindingResource::Sampler(sampler),
+        }
+    }
+
+    pub fn feedback(group: u32, binding: u32, feedback: FeedbackHandle) -> Self {
+        Self {
+            group,
+            binding,
+            resource: BindingResource::Feedback(feedback),
+        }
+    }
+
+    // At the reserved group and binding declared in globals.wgsl
+    pub fn globals() -> Self {
+        Self {
+            group: GLOBALS_GROUP,
+            binding: 0,
+            resource: BindingResource::Globals,
+        }
+    }
+
+    pub fn storage_buffer(group: u32, binding: u32, buffer: StorageBufferHandle) -> Self {
+        Self {
+            group,
+            binding,
+            resource: BindingResource::StorageBuffer(buffer),
+        }
+    }
+
+    pub fn storage_texture(group: u32, binding: u32, texture: TextureHandle) -> Self {
+        Self {
+            group,
+            binding,
+            resource: BindingResource::StorageTexture(texture),
+        }
+    }
+}
+
+// Blend presets for the color target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Blend {
+    Replace,
+    // Straight alpha, the usual "over" compositing
+    Alpha,
+    // For colors already multiplied by their alpha
+    Premultiplied,
+    Additive,
+    // Darkens the target by the drawn color, like layered glazes
+    Multiply,
+    Custom(wgpu::BlendState),
+}
+
+impl Blend {
+    pub fn state(&self) -> wgpu::BlendState {
+        match *self {
+            Blend::Replace => wgpu::BlendState::REPLACE,
+            Blend::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            Blend::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            Blend::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            Blend::Multiply => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Dst,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                // Keeps the alpha of the target
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::Zero,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+            Blend::Custom(state) => state,
+        }
+    }
+}
+
+// Pipeline state and resource bindings of a sketch. The defaults draw
+// filled, back-face culled triangle lists replacing the target color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SketchOptions {
+    pub blend: Blend,
+    // The strip index format of strip topologies is taken from the form
+    pub topology: wgpu::PrimitiveTopology,
+    pub front_face: wgpu::FrontFace,
+    pub cull_mode: Option<wgpu::Face>,
+    // Line and Point require Features::POLYGON_MODE_LINE and POLYGON_MODE_POINT
+    pub polygon_mode: wgpu::PolygonMode,
+    pub write_mask: wgpu::ColorWrites,
+    // Depth test in layers with a depth buffer, ignored in other layers
+    pub depth_compare: wgpu::CompareFunction,
+    pub depth_write: bool,
+    // Every binding the shade uses must be provided. The pipeline layout and
+    // bind groups are derived from the bindings.
+    pub bindings: Vec<Binding>,
+}
+
+impl Default for SketchOptions {
+    fn default() -> Self {
+        Self {
+            blend: Blend::Replace,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            write_mask: wgpu::ColorWrites::ALL,
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write: true,
+            bindings: vec![],
+        }
+    }
+}
+
+impl SketchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_blend(mut self, blend: Blend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn with_front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn with_polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn with_write_mask(mut self, write_mask: wgpu::ColorWrites) -> Self {
+        self.write_mask = write_mask;
+        self
+    }
+
+    pub fn with_depth(mut self, depth_compare: wgpu::CompareFunction, depth_write: bool) -> Self {
+        self.depth_compare = depth_compare;
+        self.depth_write = depth_write;
+        self
+    }
+
+    pub fn with_binding(mut self, binding: Binding) -> Self {
+        self.bindings.push(binding);
+        self
+    }
+
+    pub fn with_bindings(mut self, bindings: Vec<Binding>) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    // Binds the renderer's globals, see globals.wgsl
+    pub fn with_globals(mut self) -> Self {
+        self.set_binding(Binding::globals());
+        self
+    }
+
+    // Adds the binding, replacing any binding at the same group and index
+    pub fn set_binding(&mut self, binding: Binding) -> &Self {
+        self.bindings
+            .retain(|b| b.group != binding.group || b.binding != binding.binding);
+        self.bindings.push(binding);
+        self
+    }
+}
+
 pub struct Sketch {
-    pub pipeline: wgpu::RenderPipeline,
-    pub form_idx: usize,
+    pub shade: ShadeHandle,
+    pub form: FormHandle,
+    pub options: SketchOptions,
+    pub pipeline_layout: wgpu::PipelineLayout,
+    // One per pass format of the layers drawing the sketch
+    pub pipelines: Vec<(PassFormat, wgpu::RenderPipeline)>,
+    // One per group up to the highest bound group, set in order when drawing
+    pub bind_groups: Vec<wgpu::BindGroup>,
+    // The same groups with the feedback sides swapped, if the sketch binds
+    // any feedback
+    pub swapped_bind_groups: Option<Vec<wgpu::BindGroup>>,
 }
 
 impl Sketch {
-    pub fn new(renderer: &Renderer, shade_idx: usize, form_idx: usize) -> Self {
-        let shade = renderer
-            .shades
-            .get(shade_idx)
-            .expect("shader index invalid");
-        let form = renderer.form(form_idx);
-
-        let render_pipeline_layout =
+    // Creates pipelines for the given pass formats right away, others are
+    // added when the sketch is drawn in a layer with that format
+    pub fn new(
+        renderer: &Renderer,
+        shade_handle: ShadeHandle,
+        form_handle: FormHandle,
+        options: SketchOptions,
+        pass_formats: &[PassFormat],
+    ) -> Result<Self, RendererError> {
+        let shade = renderer.shade(shade_handle)?;
+        if shade.stage != ShadeStage::Render {
+            return Err(RendererError::ShadeStage {
+                expected: ShadeStage::Render,
+                actual: shade.stage,
+            });
+        }
+        renderer
+            .form(form_handle)?
+            .check_topology(options.topology)?;
+        let bindings = &options.bindings;
+
+        let required_features = match options.polygon_mode {
+            wgpu::PolygonMode::Fill => wgpu::Features::empty(),
+            wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        };
+        if !renderer.device.features().contains(required_features) {
+            return Err(RendererError::UnsupportedFeature(required_features));
+        }
+
+        check_bindings(shade, bindings)?;
+        let bind_group_layouts = create_bind_group_layouts(renderer, shade, bindings)?;
+        let bind_groups = create_bind_groups(renderer, bindings, &bind_group_layouts, false)?;
+        let swapped_bind_groups =
+            create_swapped_bind_groups(renderer, bindings, &bind_group_layouts)?;
+
+        let pipeline_layout =
             renderer
                 .device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Render Pipeline Layout"),
-                    bind_group_layouts: &[],
+                    bind_group_layouts: &bind_group_layouts.iter().collect::<Vec<_>>(),
                     push_constant_ranges: &[],
                 });
 
+        let mut sketch = Self {
+            shade: shade_handle,
+            form: form_handle,
+            options,
+            pipeline_layout,
+            pipelines: vec![],
+            bind_groups,
+            swapped_bind_groups,
+        };
+        for pass_format in pass_formats {
+            let pipeline = sketch.create_pipeline(renderer, *pass_format)?;
+            sketch.pipelines.push((*pass_format, pipeline));
+        }
+        Ok(sketch)
+    }
+
+    pub fn pipeline(&self, pass_format: PassFormat) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines
+            .iter()
+            .find(|(format, _)| *format == pass_format)
+            .map(|(_, pipeline)| pipeline)
+    }
+
+    pub fn pass_formats(&self) -> Vec<PassFormat> {
+        self.pipelines.iter().map(|(format, _)| *format).collect()
+    }
+
+    pub fn create_pipeline(
+        &self,
+        renderer: &Renderer,
+        pass_format: PassFormat,
+    ) -> Result<wgpu::RenderPipeline, RendererError> {
+        let shade = renderer.shade(self.shade)?;
+        let form = renderer.form(self.form)?;
+        let options = &self.options;
+
+        let strip_index_format = match is_strip(options.topology) {
+            true => form.index_format(),
+            false => None,
+        };
+
         let create_pipeline = |attr: &[wgpu::VertexBufferLayout]| {
             renderer
                 .device
                 .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
                     label: Some("Render Pipeline"),
-                    layout: Some(&render_pipeline_layout),
+                    layout: Some(&self.pipeline_layout),
                     vertex: wgpu::VertexState {
                         module: &shade.shader,
                         entry_point: "vs_main",
@@ -38,25 +351,32 @@ impl Sketch {
                         entry_point: "fs_main",
                         targets: &[wgpu::ColorTargetState {
                             format: renderer.config.format,
-                            blend: Some(wgpu::BlendState::REPLACE),
-                            write_mask: wgpu::ColorWrites::all(),
+                            blend: Some(options.blend.state()),
+                            write_mask: options.write_mask,
                         }],
                     }),
                     primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: Some(wgpu::Face::Back),
-                        // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                        polygon_mode: wgpu::PolygonMode::Fill,
+                        topology: options.topology,
+                        strip_index_format,
+                        front_face: options.front_face,
+                        cull_mode: options.cull_mode,
+                        polygon_mode: options.polygon_mode,
                         // Requires Features::DEPTH_CLAMPING
                         unclipped_depth: false,
                         // Requires Features::CONSERVATIVE_RASTERIZATION
                         conservative: false,
                     },
-                    depth_stencil: None,
+                    depth_stencil: pass_format
+                        .depth_format
+                        .map(|format| wgpu::DepthStencilState {
+                            format,
+                            depth_write_enabled: options.depth_write,
+                            depth_compare: options.depth_compare,
+                            stencil: wgpu::StencilState::default(),
+                            bias: wgpu::DepthBiasState::default(),
+                        }),
                     multisample: wgpu::MultisampleState {
-                        count: 1,
+                        count: pass_format.sample_count,
                         mask: !0,
                         alpha_to_coverage_enabled: false,
                     },
@@ -64,26 +384,220 @@ impl Sketch {
                 })
         };
 
-        let pipeline = match form {
-            Form::SimpleRange { .. } => create_pipeline(&[]),
-            Form::Vertices(buf) => {
-                let layout = wgpu::VertexBufferLayout {
-                    array_stride: buf.array_stride,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: buf.attributes.as_slice(),
-                };
-                create_pipeline(&[layout])
-            }
-            Form::IndexedVertices(buf) => {
-                let layout = wgpu::VertexBufferLayout {
-                    array_stride: buf.array_stride,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: buf.attributes.as_slice(),
-                };
-                create_pipeline(&[layout])
+        let pipeline = create_pipeline(&form.buffer_layouts());
+        Ok(pipeline)
+    }
+
+    // The bind groups to draw with, depending on the renderer's feedback sides
+    pub fn bind_groups(&self, swapped: bool) -> &[wgpu::BindGroup] {
+        match (&self.swapped_bind_groups, swapped) {
+            (Some(bind_groups), true) => bind_groups,
+            _ => &self.bind_groups,
+        }
+    }
+
+    pub fn uses(&self, resource: BindingResource) -> bool {
+        self.options.bindings.iter().any(|b| b.resource == resource)
+    }
+}
+
+// Every binding the shade uses must be provided
+pub(crate) fn check_bindings(shade: &Shade, bindings: &[Binding]) -> Result<(), RendererError> {
+    for (group, binding) in shade.bindings.iter() {
+        if !bindings
+            .iter()
+            .any(|b| b.group == *group && b.binding == *binding)
+        {
+            return Err(RendererError::MissingBinding {
+                group: *group,
+                binding: *binding,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Creates a layout for all groups up to the highest one used. Groups
+// without bindings get an empty layout. The shade decides the visibility
+// and whether storage buffers are writable.
+pub(crate) fn create_bind_group_layouts(
+    renderer: &Renderer,
+    shade: &Shade,
+    bindings: &[Binding],
+) -> Result<Vec<wgpu::BindGroupLayout>, RendererError> {
+    let group_count = bindings.iter().map(|b| b.group + 1).max().unwrap_or(0);
+    let mut layouts = vec![];
+
+    for group in 0..group_count {
+        let group_bindings: Vec<&Binding> = bindings.iter().filter(|b| b.group == group).collect();
+        for (i, binding) in group_bindings.iter().enumerate() {
+            if group_bindings[..i]
+                .iter()
+                .any(|b| b.binding == binding.binding)
+            {
+                return Err(RendererError::DuplicateBinding {
+                    group,
+                    binding: binding.binding,
+                });
             }
-        };
+        }
+
+        let mut entries = vec![];
+        for binding in group_bindings {
+            let writable = match binding.resource {
+                BindingResource::StorageBuffer(_) => shade
+                    .writable_bindings
+                    .contains(&(binding.group, binding.binding)),
+                BindingResource::StorageTexture(_) => true,
+                _ => false,
+            };
+            let ty = match binding.resource {
+                BindingResource::Uniform(handle) => {
+                    renderer.uniform(handle)?;
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    }
+                }
+                BindingResource::Globals => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                BindingResource::Texture(handle) => texture_binding_type(renderer.texture(handle)?),
+                BindingResource::Feedback(handle) => {
+                    let target = renderer.feedback(handle)?.previous(false);
+                    texture_binding_type(renderer.texture(target)?)
+                }
+                BindingResource::Sampler(handle) => {
+                    wgpu::BindingType::Sampler(renderer.sampler(handle)?.binding_type())
+                }
+                BindingResource::StorageBuffer(handle) => {
+                    renderer.storage_buffer(handle)?;
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: !writable,
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    }
+                }
+                BindingResource::StorageTexture(handle) => {
+                    let texture = renderer.texture(handle)?;
+                    if !texture.is_storage() {
+                        return Err(RendererError::NotStorage(format!("{:?}", handle)));
+                    }
+                    wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: texture.format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    }
+                }
+            };
+            let visibility = match shade.stage {
+                ShadeStage::Compute => wgpu::ShaderStages::COMPUTE,
+                // Vertex shaders cannot write to storage
+                ShadeStage::Render if writable => wgpu::ShaderStages::FRAGMENT,
+                ShadeStage::Render => wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            };
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility,
+                ty,
+                count: None,
+            });
+        }
+
+        layouts.push(
+            renderer
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Sketch Bind Group Layout"),
+                    entries: &entries,
+                }),
+        );
+    }
+
+    Ok(layouts)
+}
+
+fn texture_binding_type(texture: &Texture) -> wgpu::BindingType {
+    wgpu::BindingType::Texture {
+        sample_type: texture.sample_type(),
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+    }
+}
+
+// Creates one bind group per layout. `swapped` selects which side of bound
+// feedbacks is used.
+pub(crate) fn create_bind_groups(
+    renderer: &Renderer,
+    bindings: &[Binding],
+    layouts: &[wgpu::BindGroupLayout],
+    swapped: bool,
+) -> Result<Vec<wgpu::BindGroup>, RendererError> {
+    let mut groups = vec![];
+
+    for (group, layout) in layouts.iter().enumerate() {
+        let mut entries = vec![];
+        for binding in bindings.iter().filter(|b| b.group == group as u32) {
+            let resource = match binding.resource {
+                BindingResource::Uniform(handle) => {
+                    renderer.uniform(handle)?.buffer.as_entire_binding()
+                }
+                BindingResource::Globals => renderer.globals_uniform().buffer.as_entire_binding(),
+                BindingResource::Texture(handle) => {
+                    wgpu::BindingResource::TextureView(&renderer.texture(handle)?.view)
+                }
+                BindingResource::Feedback(handle) => {
+                    let target = renderer.feedback(handle)?.previous(swapped);
+                    wgpu::BindingResource::TextureView(&renderer.texture(target)?.view)
+                }
+                BindingResource::Sampler(handle) => {
+                    wgpu::BindingResource::Sampler(&renderer.sampler(handle)?.sampler)
+                }
+                BindingResource::StorageBuffer(handle) => {
+                    renderer.storage_buffer(handle)?.buffer.as_entire_binding()
+                }
+                BindingResource::StorageTexture(handle) => {
+                    wgpu::BindingResource::TextureView(&renderer.texture(handle)?.view)
+                }
+            };
+            entries.push(wgpu::BindGroupEntry {
+                binding: binding.binding,
+                resource,
+            });
+        }
+
+        groups.push(
+            renderer
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Sketch Bind Group"),
+                    layout,
+                    entries: &entries,
+                }),
+        );
+    }
+
+    Ok(groups)
+}
 
-        Self { pipeline, form_idx }
+// The bind groups with the other feedback sides, only needed if any
+// feedback is bound
+pub(crate) fn create_swapped_bind_groups(
+    renderer: &Renderer,
+    bindings: &[Binding],
+    layouts: &[wgpu::BindGroupLayout],
+) -> Result<Option<Vec<wgpu::BindGroup>>, RendererError> {
+    if bindings
+        .iter()
+        .any(|b| matches!(b.resource, BindingResource::Feedback(_)))
+    {
+        Ok(Some(create_bind_groups(renderer, bindings, layouts, true)?))
+    } else {
+        Ok(None)
     }
 }
@@ -0,0 +1,44 @@
+use bytemuck::Pod;
+use wgpu::util::DeviceExt;
+
+use super::Renderer;
+
+// A buffer computes read and write, e.g. particle positions. Sketches can
+// read it too, indexed by the vertex or instance index.
+pub struct StorageBuffer {
+    pub buffer: wgpu::Buffer,
+    pub size: u64,
+}
+
+impl StorageBuffer {
+    pub fn new<T: Pod>(renderer: &Renderer, data: &[T]) -> Self {
+        let contents: &[u8] = bytemuck::cast_slice(data);
+        Self {
+            buffer: renderer
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Storage Buffer"),
+                    contents,
+                    usage: Self::usage(),
+                }),
+            size: contents.len() as u64,
+        }
+    }
+
+    // Storage buffers start out zeroed
+    pub fn zeroed(renderer: &Renderer, size: u64) -> Self {
+        Self {
+            buffer: renderer.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Storage Buffer"),
+                size,
+                usage: Self::usage(),
+                mapped_at_creation: false,
+            }),
+            size,
+        }
+    }
+
+    fn usage() -> wgpu::BufferUsages {
+        wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC
+    }
+}
@@ -0,0 +1,287 @@
+use std::num::NonZeroU32;
+
+use winit::dpi::PhysicalSize;
+
+use super::{Renderer, RendererError};
+
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub size: PhysicalSize<u32>,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+    // Set for textures layers can render into
+    pub target_size: Option<TargetSize>,
+}
+
+// Size of a render target. Relative targets are a fraction of the surface
+// size and are recreated when the surface is resized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetSize {
+    Fixed(PhysicalSize<u32>),
+    Relative(f32),
+}
+
+impl TargetSize {
+    pub fn resolve(&self, surface_size: PhysicalSize<u32>) -> PhysicalSize<u32> {
+        match *self {
+            TargetSize::Fixed(size) => size,
+            TargetSize::Relative(scale) => {
+                let scaled = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+                PhysicalSize::new(scaled(surface_size.width), scaled(surface_size.height))
+            }
+        }
+    }
+
+    pub fn is_relative(&self) -> bool {
+        matches!(self, TargetSize::Relative(_))
+    }
+}
+
+impl Texture {
+    // Images are uploaded as sRGB, so shaders sample linear colors
+    pub fn from_image(renderer: &Renderer, image: &image::DynamicImage) -> Self {
+        let image = image.to_rgba8();
+        let size = PhysicalSize::new(image.width(), image.height());
+        let texture = Self::empty(renderer, size, wgpu::TextureFormat::Rgba8UnormSrgb);
+        texture.write(renderer, image.as_raw());
+        texture
+    }
+
+    pub fn empty(
+        renderer: &Renderer,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC;
+        Self::create(renderer, size, format, usage, None)
+    }
+
+    // Textures computes can write into. The format must support storage,
+    // e.g. Rgba8Unorm, Rgba16Float or R32Float, but not sRGB formats.
+    pub fn storage(
+        renderer: &Renderer,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC;
+        Self::create(renderer, size, format, usage, None)
+    }
+
+    // Render targets share the surface format, so sketches drawn into them
+    // can use the same pipelines as sketches drawn to the screen
+    pub fn render_target(renderer: &Renderer, target_size: TargetSize) -> Self {
+        let usage = wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC;
+        Self::create(
+            renderer,
+            target_size.resolve(renderer.size),
+            renderer.config.format,
+            usage,
+            Some(target_size),
+        )
+    }
+
+    fn create(
+        renderer: &Renderer,
+        size: PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        target_size: Option<TargetSize>,
+    ) -> Self {
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(if target_size.is_some() {
+                "Render Target Texture"
+            } else {
+                "Texture"
+            }),
+            size: extent(size),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            size,
+            format,
+            usage,
+            target_size,
+        }
+    }
+
+    pub fn is_render_target(&self) -> bool {
+        self.target_size.is_some()
+    }
+
+    pub fn is_storage(&self) -> bool {
+        self.usage.contains(wgpu::TextureUsages::STORAGE_BINDING)
+    }
+
+    // Number of bytes `write` expects for the whole texture
+    pub fn byte_size(&self) -> u64 {
+        let block_size = self.format.describe().block_size as u64;
+        self.size.width as u64 * self.size.height as u64 * block_size
+    }
+
+    // Uploads tightly packed rows of texels, without any row padding
+    pub fn write(&self, renderer: &Renderer, data: &[u8]) {
+        let block_size = self.format.describe().block_size as u32;
+        renderer.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(self.size.width * block_size),
+                rows_per_image: NonZeroU32::new(self.size.height),
+            },
+            extent(self.size),
+        );
+    }
+
+    // Scales the image to the texture size and converts it to the texture
+    // format. Only 8 bit RGBA and BGRA formats are supported.
+    pub fn write_image(
+        &self,
+        renderer: &Renderer,
+        image: &image::DynamicImage,
+    ) -> Result<(), RendererError> {
+        let mut image = image
+            .resize_exact(
+                self.size.width,
+                self.size.height,
+                image::imageops::FilterType::Triangle,
+            )
+            .to_rgba8();
+        match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {}
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                for pixel in image.pixels_mut() {
+                    pixel.0.swap(0, 2);
+                }
+            }
+            format => return Err(RendererError::TextureFormat(format)),
+        }
+        self.write(renderer, image.as_raw());
+        Ok(())
+    }
+
+    pub fn sample_type(&self) -> wgpu::TextureSampleType {
+        self.format.describe().sample_type
+    }
+}
+
+fn extent(size: PhysicalSize<u32>) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width: size.width,
+        height: size.height,
+        depth_or_array_layers: 1,
+    }
+}
+
+// How a texture is filtered and what happens to coordinates outside of [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerOptions {
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+impl SamplerOptions {
+    pub fn linear() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+
+    // Keeps hard texel edges, e.g. for pixel art or data textures
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Self::linear()
+        }
+    }
+
+    pub fn with_filter(
+        mut self,
+        mag_filter: wgpu::FilterMode,
+        min_filter: wgpu::FilterMode,
+    ) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self
+    }
+
+    pub fn with_address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self
+    }
+
+    pub fn with_address_modes(
+        mut self,
+        address_mode_u: wgpu::AddressMode,
+        address_mode_v: wgpu::AddressMode,
+    ) -> Self {
+        self.address_mode_u = address_mode_u;
+        self.address_mode_v = address_mode_v;
+        self
+    }
+
+    fn is_filtering(&self) -> bool {
+        self.mag_filter == wgpu::FilterMode::Linear || self.min_filter == wgpu::FilterMode::Linear
+    }
+}
+
+pub struct Sampler {
+    pub sampler: wgpu::Sampler,
+    pub options: SamplerOptions,
+}
+
+impl Sampler {
+    pub fn new(renderer: &Renderer, options: SamplerOptions) -> Self {
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Sampler"),
+            address_mode_u: options.address_mode_u,
+            address_mode_v: options.address_mode_v,
+            mag_filter: options.mag_filter,
+            min_filter: options.min_filter,
+            ..Default::default()
+        });
+        Self { sampler, options }
+    }
+
+    pub fn binding_type(&self) -> wgpu::SamplerBindingType {
+        if self.options.is_filtering() {
+            wgpu::SamplerBindingType::Filtering
+        } else {
+            wgpu::SamplerBindingType::NonFiltering
+        }
+    }
+}
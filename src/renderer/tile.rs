@@ -0,0 +1,100 @@
+use glam::{vec3, Mat4};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+// A rectangle of a canvas larger than the renderer, e.g. of a print
+// rendered in tiles. Renderers not rendering tiles have a single tile
+// covering the whole canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    // Top left corner in canvas pixels
+    pub offset: PhysicalPosition<u32>,
+    pub size: PhysicalSize<u32>,
+    pub canvas_size: PhysicalSize<u32>,
+}
+
+impl Tile {
+    pub fn full(size: PhysicalSize<u32>) -> Self {
+        Self {
+            offset: PhysicalPosition::new(0, 0),
+            size,
+            canvas_size: size,
+        }
+    }
+
+    // Maps clip space of the canvas to clip space of the tile. Sketches
+    // multiply their projection by it from the left and compute aspect
+    // ratios from the canvas size, so each tile shows its part of the canvas.
+    pub fn clip_transform(&self) -> Mat4 {
+        let canvas_width = self.canvas_size.width as f32;
+        let canvas_height = self.canvas_size.height as f32;
+        let width = self.size.width as f32;
+        let height = self.size.height as f32;
+        let x = self.offset.x as f32;
+        let y = self.offset.y as f32;
+        // Clip space y points up, pixel rows down
+        let translation = vec3(
+            (canvas_width - 2.0 * x - width) / width,
+            (height - canvas_height + 2.0 * y) / height,
+            0.0,
+        );
+        let scale = vec3(canvas_width / width, canvas_height / height, 1.0);
+        Mat4::from_translation(translation) * Mat4::from_scale(scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::{vec2, Vec2};
+
+    fn tile(x: u32, y: u32, width: u32, height: u32) -> Tile {
+        Tile {
+            offset: PhysicalPosition::new(x, y),
+            size: PhysicalSize::new(width, height),
+            canvas_size: PhysicalSize::new(200, 100),
+        }
+    }
+
+    // Canvas clip space position in tile clip space
+    fn map(tile: &Tile, canvas: Vec2) -> Vec2 {
+        let clip = tile.clip_transform() * canvas.extend(0.0).extend(1.0);
+        vec2(clip.x, clip.y) / clip.w
+    }
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-5),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn full_tile_is_identity() {
+        let full = Tile::full(PhysicalSize::new(200, 100));
+        assert!(full.clip_transform().abs_diff_eq(Mat4::IDENTITY, 1e-6));
+    }
+
+    #[test]
+    fn top_left_tile() {
+        let top_left = tile(0, 0, 100, 50);
+        assert_near(map(&top_left, vec2(-1.0, 1.0)), vec2(-1.0, 1.0));
+        assert_near(map(&top_left, vec2(0.0, 0.0)), vec2(1.0, -1.0));
+    }
+
+    #[test]
+    fn bottom_right_tile() {
+        let bottom_right = tile(100, 50, 100, 50);
+        assert_near(map(&bottom_right, vec2(0.0, 0.0)), vec2(-1.0, 1.0));
+        assert_near(map(&bottom_right, vec2(1.0, -1.0)), vec2(1.0, -1.0));
+    }
+
+    #[test]
+    fn uneven_edge_tile() {
+        // The rightmost 50 pixels of the top 80 rows
+        let edge = tile(150, 0, 50, 80);
+        assert_near(map(&edge, vec2(0.5, 1.0)), vec2(-1.0, 1.0));
+        assert_near(map(&edge, vec2(1.0, -0.6)), vec2(1.0, -1.0));
+    }
+}
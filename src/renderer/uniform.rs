@@ -0,0 +1,26 @@
+use bytemuck::Pod;
+use wgpu::util::DeviceExt;
+
+use super::Renderer;
+
+pub struct Uniform {
+    pub buffer: wgpu::Buffer,
+    pub size: u64,
+}
+
+impl Uniform {
+    pub fn new<T: Pod>(renderer: &Renderer, data: &T) -> Self {
+        Self::with_device(&renderer.device, data)
+    }
+
+    pub fn with_device<T: Pod>(device: &wgpu::Device, data: &T) -> Self {
+        Self {
+            buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Uniform Buffer"),
+                contents: bytemuck::bytes_of(data),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            size: std::mem::size_of::<T>() as u64,
+        }
+    }
+}
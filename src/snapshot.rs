@@ -0,0 +1,272 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    app::{AppState, AppView},
+    clock::FrameClock,
+    renderer::prelude::{Renderer, RendererError},
+};
+
+// Set this environment variable to create or overwrite reference images
+// with the current rendering instead of comparing against them.
+pub const UPDATE_ENV_VAR: &str = "PAINTINGS_UPDATE_SNAPSHOTS";
+
+// Renders an AppState/AppView pair headlessly and compares the result
+// against a reference PNG.
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    pub tolerance: u8,
+}
+
+impl Snapshot {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            frames: 1,
+            tolerance: 0,
+        }
+    }
+
+    pub fn with_frames(mut self, frames: u32) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    // Maximum allowed difference per color channel for a pixel to match
+    pub fn with_tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    // Runs update and render for the configured number of frames and
+    // returns the final image.
+    pub fn render<S: AppState, V: AppView<S>>(&self) -> Result<image::RgbaImage, RendererError> {
+        let mut renderer = pollster::block_on(Renderer::new_headless(self.width, self.height))?;
+        let mut state = S::init();
+        let mut view = V::init(&mut renderer, &state);
+        // Time advances as if snapshots ran at 60 frames per second
+        let mut clock = FrameClock::new().deterministic(60.0);
+        for _ in 0..self.frames {
+            clock.tick();
+            renderer.set_time(clock.time(), clock.delta(), clock.frame());
+            state.update(&clock);
+            view.render(&mut renderer, &state, &clock)?;
+            renderer.end_frame();
+        }
+        renderer.read_image()
+    }
+
+    // Compares the rendering against the reference image. A missing reference
+    // is an error unless `UPDATE_ENV_VAR` is set, so a CI run without the
+    // committed image fails. On mismatch, the actual image and a diff image
+    // are written next to the reference.
+    pub fn compare<S: AppState, V: AppView<S>>(
+        &self,
+        reference: impl AsRef<Path>,
+    ) -> Result<(), SnapshotError> {
+        let reference = reference.as_ref();
+        let actual = self.render::<S, V>()?;
+
+        if std::env::var_os(UPDATE_ENV_VAR).is_some() {
+            log::warn!("writing snapshot reference {}", reference.display());
+            if let Some(dir) = reference.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            actual.save(reference)?;
+            return Ok(());
+        }
+        if !reference.exists() {
+            actual.save(reference.with_extension("actual.png"))?;
+            return Err(SnapshotError::MissingReference(reference.to_path_buf()));
+        }
+
+        let expected = image::open(reference)?.to_rgba8();
+        if expected.dimensions() != actual.dimensions() {
+            actual.save(reference.with_extension("actual.png"))?;
+            return Err(SnapshotError::SizeMismatch {
+                expected: expected.dimensions(),
+                actual: actual.dimensions(),
+            });
+        }
+
+        let (mismatched_pixels, diff) = diff_images(&expected, &actual, self.tolerance);
+        if mismatched_pixels > 0 {
+            actual.save(reference.with_extension("actual.png"))?;
+            diff.save(reference.with_extension("diff.png"))?;
+            return Err(SnapshotError::Mismatch {
+                mismatched_pixels,
+                total_pixels: (actual.width() * actual.height()) as usize,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Like `compare`, but panics with a readable message for use in tests
+    pub fn assert<S: AppState, V: AppView<S>>(&self, reference: impl AsRef<Path>) {
+        let reference = reference.as_ref();
+        if let Err(e) = self.compare::<S, V>(reference) {
+            panic!("snapshot {} failed: {}", reference.display(), e);
+        }
+    }
+}
+
+// Counts the pixels where any channel differs by more than the tolerance.
+// Images of different sizes are compared over the larger size, pixels
+// missing from either image count as mismatched. The diff image shows
+// mismatches in red on top of a faded copy of the expected image.
+pub fn diff_images(
+    expected: &image::RgbaImage,
+    actual: &image::RgbaImage,
+    tolerance: u8,
+) -> (usize, image::RgbaImage) {
+    let width = expected.width().max(actual.width());
+    let height = expected.height().max(actual.height());
+    let mut mismatched_pixels = 0;
+    let diff = image::RgbaImage::from_fn(width, height, |x, y| {
+        let in_bounds = |image: &image::RgbaImage| x < image.width() && y < image.height();
+        if !in_bounds(expected) || !in_bounds(actual) {
+            mismatched_pixels += 1;
+            return image::Rgba([255, 0, 0, 255]);
+        }
+        let e = expected.get_pixel(x, y);
+        let a = actual.get_pixel(x, y);
+        let matches =
+            e.0.iter()
+                .zip(a.0.iter())
+                .all(|(e, a)| e.abs_diff(*a) <= tolerance);
+        if matches {
+            let [r, g, b, _] = e.0;
+            let luma = ((r as u32 + g as u32 + b as u32) / 3 / 4) as u8;
+            image::Rgba([luma, luma, luma, 255])
+        } else {
+            mismatched_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        }
+    });
+    (mismatched_pixels, diff)
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    MissingReference(PathBuf),
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        mismatched_pixels: usize,
+        total_pixels: usize,
+    },
+    Renderer(RendererError),
+    Io(std::io::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::MissingReference(path) => write!(
+                f,
+                "reference {} does not exist, set {} to create it",
+                path.display(),
+                UPDATE_ENV_VAR
+            ),
+            SnapshotError::SizeMismatch { expected, actual } => write!(
+                f,
+                "image size {}x{} does not match reference size {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            SnapshotError::Mismatch {
+                mismatched_pixels,
+                total_pixels,
+            } => write!(
+                f,
+                "{} of {} pixels differ from the reference",
+                mismatched_pixels, total_pixels
+            ),
+            SnapshotError::Renderer(e) => write!(f, "rendering failed: {}", e),
+            SnapshotError::Io(e) => write!(f, "io error: {}", e),
+            SnapshotError::Image(e) => write!(f, "image error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<RendererError> for SnapshotError {
+    fn from(e: RendererError) -> Self {
+        SnapshotError::Renderer(e)
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for SnapshotError {
+    fn from(e: image::ImageError) -> Self {
+        SnapshotError::Image(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(width: u32, height: u32, pixel: [u8; 4]) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba(pixel))
+    }
+
+    #[test]
+    fn identical_images_match() {
+        let image = filled(4, 3, [10, 20, 30, 255]);
+        let (mismatched, diff) = diff_images(&image, &image, 0);
+        assert_eq!(mismatched, 0);
+        assert_eq!(diff.dimensions(), (4, 3));
+    }
+
+    #[test]
+    fn differences_up_to_the_tolerance_match() {
+        let expected = filled(2, 2, [100, 100, 100, 255]);
+        let within = filled(2, 2, [103, 97, 100, 255]);
+        let beyond = filled(2, 2, [104, 100, 100, 255]);
+        assert_eq!(diff_images(&expected, &within, 3).0, 0);
+        assert_eq!(diff_images(&expected, &beyond, 3).0, 4);
+    }
+
+    #[test]
+    fn alpha_is_compared_too() {
+        let expected = filled(1, 1, [0, 0, 0, 255]);
+        let actual = filled(1, 1, [0, 0, 0, 0]);
+        assert_eq!(diff_images(&expected, &actual, 254).0, 1);
+    }
+
+    #[test]
+    fn mismatches_are_marked_red() {
+        let expected = filled(2, 1, [200, 200, 200, 255]);
+        let mut actual = expected.clone();
+        actual.put_pixel(1, 0, image::Rgba([0, 0, 0, 255]));
+        let (mismatched, diff) = diff_images(&expected, &actual, 0);
+        assert_eq!(mismatched, 1);
+        assert_eq!(diff.get_pixel(0, 0).0, [50, 50, 50, 255]);
+        assert_eq!(diff.get_pixel(1, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn pixels_outside_the_smaller_image_mismatch() {
+        let expected = filled(4, 2, [1, 2, 3, 255]);
+        let actual = filled(2, 3, [1, 2, 3, 255]);
+        let (mismatched, diff) = diff_images(&expected, &actual, 0);
+        // 4x3 compared, of which only the 2x2 overlap matches
+        assert_eq!(diff.dimensions(), (4, 3));
+        assert_eq!(mismatched, 12 - 4);
+    }
+}
@@ -0,0 +1,113 @@
+use paintings::{prelude::*, snapshot::Snapshot};
+use winit::{event::WindowEvent, window::Window};
+
+// These need a graphics adapter, run them with `cargo test -- --ignored`.
+// Set PAINTINGS_UPDATE_SNAPSHOTS=1 to update the reference images.
+fn reference(name: &str) -> String {
+    format!(
+        "{}/tests/snapshots/{}.png",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+struct State;
+
+impl AppState for State {
+    fn init() -> Self {
+        Self
+    }
+
+    fn input(&mut self, _event: &WindowEvent, _window: &Window) -> bool {
+        false
+    }
+
+    fn update(&mut self, _clock: &FrameClock) {}
+}
+
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+// A layer without sketches, only clearing its target
+struct ClearView {
+    layer: LayerHandle,
+}
+
+impl AppView<State> for ClearView {
+    fn init(renderer: &mut Renderer, _state: &State) -> Self {
+        let layer = renderer.make_layer(vec![]).unwrap();
+        renderer
+            .layer_mut(layer)
+            .unwrap()
+            .set_clear_color(Some(CLEAR_COLOR));
+        Self { layer }
+    }
+
+    fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        _state: &State,
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
+        renderer.render_layer(self.layer)
+    }
+
+    fn resize(&mut self, _window: &Window) {}
+}
+
+// The first sketch of the wgpu-tutorial3-pipeline binary
+struct TriangleView {
+    layer: LayerHandle,
+}
+
+impl AppView<State> for TriangleView {
+    fn init(renderer: &mut Renderer, _state: &State) -> Self {
+        let form = renderer.make_form_simple_range(3).unwrap();
+        let shade = renderer
+            .make_shade(include_str!(
+                "../src/bin/wgpu-tutorial3-pipeline/shader1.wgsl"
+            ))
+            .unwrap();
+        let sketch = renderer
+            .make_sketch(shade, form, SketchOptions::new())
+            .unwrap();
+        let layer = renderer.make_layer(vec![sketch]).unwrap();
+        renderer
+            .layer_mut(layer)
+            .unwrap()
+            .set_clear_color(Some(CLEAR_COLOR));
+        Self { layer }
+    }
+
+    fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        _state: &State,
+        _clock: &FrameClock,
+    ) -> Result<(), RendererError> {
+        renderer.render_layer(self.layer)
+    }
+
+    fn resize(&mut self, _window: &Window) {}
+}
+
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn clear_color() {
+    // Converting the clear color to sRGB may round either way
+    Snapshot::new(64, 64)
+        .with_tolerance(1)
+        .assert::<State, ClearView>(reference("clear_color"));
+}
+
+#[test]
+#[ignore = "needs a graphics adapter"]
+fn tutorial3_triangle() {
+    Snapshot::new(64, 64)
+        .with_tolerance(1)
+        .assert::<State, TriangleView>(reference("tutorial3_triangle"));
+}